@@ -1,5 +1,8 @@
-use clap::Parser;
-use serde::Deserialize;
+use crate::secrets::NestedValues;
+use crate::sources::FileFormat;
+use crate::transform::Transforms;
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Deserializer};
 use std::{collections::HashMap, fs, io, path};
 
 const DEFAULT_CONFIG: &str = ".scrtsync.json";
@@ -24,11 +27,41 @@ pub enum ConfigError {
         source: url::ParseError,
     },
 
+    #[error("`{field}` in preset '{preset}' must not be empty")]
+    EmptyPresetField { preset: String, field: &'static str },
+
+    #[error("{mode} can only be used with a single `{field}` source")]
+    SingleSourceOnly {
+        mode: &'static str,
+        field: &'static str,
+    },
+
     #[error("preset '{0}' not found in config file")]
     PresetNotFound(String),
 
+    #[error("no presets defined in config file")]
+    NoPresets,
+
+    #[error("group '{0}' has the same name as a preset")]
+    GroupShadowsPreset(String),
+
+    #[error("group '{group}' refers to unknown preset '{preset}'")]
+    UnknownGroupMember { group: String, preset: String },
+
     #[error("must provide either a preset or both --from and --to arguments")]
     MissingArguments,
+
+    #[error("--format {0} can only be used with --diff")]
+    FormatRequiresDiff(&'static str),
+
+    #[error("--format {0} cannot be used with --diff")]
+    FormatConflictsWithDiff(FileFormat),
+
+    #[error("run needs --from or a single preset, and a command after `--`, e.g. `scrtsync run --from vault://secret/app -- npm start`")]
+    InvalidRun,
+
+    #[error("a command after `--` can only be given to `scrtsync run`")]
+    CommandRequiresRun,
 }
 
 /// Synchronize secrets between different sources
@@ -39,42 +72,181 @@ pub struct Args {
     #[arg(short, long, default_value_t = String::from(DEFAULT_CONFIG))]
     pub config: String,
 
-    /// From where to pull secrets
+    /// From where to pull secrets. Repeat to layer sources, later ones taking precedence.
     #[arg(short, long)]
-    pub from: Option<String>,
+    pub from: Vec<String>,
 
-    /// To where to output secrets
+    /// To where to output secrets. Repeat to write the same secrets to several targets.
     #[arg(short, long)]
-    pub to: Option<String>,
+    pub to: Vec<String>,
 
     /// Show a diff between --from and --to without writing any secrets
     #[arg(short = 'd', long)]
     pub diff: bool,
 
-    /// An optional preset defined in a config file
-    pub preset: Option<String>,
+    /// Output format for --diff, or for std:// and file:// targets when syncing
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// Include SHA-256 hashes of old and new values in JSON diffs
+    #[arg(long, requires = "diff")]
+    pub hash_values: bool,
+
+    /// How values are shown in text diffs [default: none on a terminal, redact otherwise]
+    #[arg(long, value_enum, requires = "diff")]
+    pub mask: Option<Mask>,
+
+    /// With --diff, exit with 1 when secrets differ and 2 or higher on errors
+    #[arg(long, visible_alias = "check", requires = "diff")]
+    pub exit_code: bool,
+
+    /// Sync every secret below the --from path to the same relative path below --to
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Add and update keys in --to, keeping keys that only exist there
+    #[arg(short, long)]
+    pub merge: bool,
+
+    /// Keep a timestamped copy of file:// targets before changing them
+    #[arg(long, conflicts_with = "diff")]
+    pub backup: bool,
+
+    /// Show the changes and backend operations a sync would perform without writing
+    #[arg(short = 'n', long, conflicts_with = "diff")]
+    pub dry_run: bool,
+
+    /// Only sync keys matching this glob, e.g. `DB_*`. Can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip keys matching this glob, e.g. `*_ADMIN_*`. Can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Run every preset in the config file
+    #[arg(short, long, conflicts_with = "presets")]
+    pub all: bool,
+
+    /// Presets or preset groups defined in a config file, run in order
+    #[arg(value_name = "PRESET")]
+    pub presets: Vec<String>,
+
+    /// With `run`, the command to start with the secrets in its environment
+    #[arg(last = true, value_name = "COMMAND")]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human-readable output
+    #[default]
+    Text,
+
+    /// Machine-readable JSON, for --diff
+    Json,
+
+    /// POSIX shell `export` lines, for `eval "$(scrtsync pull --format sh)"`
+    Sh,
+
+    /// fish `set -gx` lines
+    Fish,
+
+    /// PowerShell `$env:` assignments
+    Powershell,
+
+    /// Unquoted lines for `docker run --env-file`
+    Docker,
+}
+
+impl Format {
+    /// How targets are written in this format, for formats that are not
+    /// about diffs.
+    pub fn target_format(self) -> Option<FileFormat> {
+        match self {
+            Format::Text | Format::Json => None,
+            Format::Sh => Some(FileFormat::Sh),
+            Format::Fish => Some(FileFormat::Fish),
+            Format::Powershell => Some(FileFormat::PowerShell),
+            Format::Docker => Some(FileFormat::Docker),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Mask {
+    /// Show values in full
+    #[default]
+    None,
+
+    /// Replace every value with `***`
+    Redact,
+
+    /// Show the first and last two characters of longer values
+    Partial,
+
+    /// Show a short SHA-256 fingerprint of each value
+    Hash,
 }
 
 impl Args {
+    /// The `init` or `run` command, given in place of the first preset.
+    pub fn subcommand(&self) -> Option<&str> {
+        self.presets
+            .first()
+            .map(String::as_str)
+            .filter(|name| matches!(*name, "init" | "run"))
+    }
+
+    /// The preset and group names, without any subcommand.
+    pub fn preset_names(&self) -> &[String] {
+        let skip = usize::from(self.subcommand().is_some());
+        &self.presets[skip..]
+    }
+
     pub fn validate(&self, config: &Config) -> Result<(), ConfigError> {
-        // If preset is provided, ensure it exists
-        if let Some(ref preset_name) = self.preset {
-            if preset_name != "init" && !config.presets.contains_key(preset_name) {
-                return Err(ConfigError::PresetNotFound(preset_name.clone()));
+        // Ensure every preset or group exists
+        let presets = config.resolve_presets(self.preset_names(), self.all)?;
+        let has_preset = !presets.is_empty();
+
+        match self.subcommand() {
+            Some("init") => return Ok(()),
+            Some("run") => {
+                if presets.len() > 1
+                    || (self.from.is_empty() && !has_preset)
+                    || self.command.is_empty()
+                {
+                    return Err(ConfigError::InvalidRun);
+                }
+
+                return Ok(());
             }
+            _ => {}
+        }
+
+        if !self.command.is_empty() {
+            return Err(ConfigError::CommandRequiresRun);
+        }
+
+        if self.format == Format::Json && !self.diff {
+            return Err(ConfigError::FormatRequiresDiff("json"));
+        }
+
+        if let Some(format) = self.format.target_format().filter(|_| self.diff) {
+            return Err(ConfigError::FormatConflictsWithDiff(format));
         }
 
         if self.diff {
             // Diff mode: need both sides (preset supplies both, or both --from and --to)
-            let has_from = self.preset.is_some() || self.from.is_some();
-            let has_to = self.preset.is_some() || self.to.is_some();
+            let has_from = has_preset || !self.from.is_empty();
+            let has_to = has_preset || !self.to.is_empty();
 
             if !has_from || !has_to {
                 return Err(ConfigError::MissingArguments);
             }
         } else {
             // Sync mode: need both sides
-            if self.preset.is_none() && (self.from.is_none() || self.to.is_none()) {
+            if !has_preset && (self.from.is_empty() || self.to.is_empty()) {
                 return Err(ConfigError::MissingArguments);
             }
         }
@@ -86,49 +258,325 @@ impl Args {
 #[derive(Debug, Deserialize, Default)]
 pub struct Config {
     pub presets: HashMap<String, PresetConfig>,
+
+    /// Named lists of presets that are run together
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PresetConfig {
-    pub from: String,
-    pub to: String,
+    /// One origin, or several layered in order of precedence
+    #[serde(deserialize_with = "one_or_many")]
+    pub from: Vec<String>,
+
+    /// One target, or several that all receive the same secrets
+    #[serde(deserialize_with = "one_or_many")]
+    pub to: Vec<String>,
+
+    /// Sync every secret below `from` to the same relative path below `to`
+    #[serde(default)]
+    pub recursive: bool,
+
+    /// Add and update keys in `to`, keeping keys that only exist there
+    #[serde(default)]
+    pub merge: bool,
+
+    /// Keep a timestamped copy of `file://` targets before changing them
+    #[serde(default)]
+    pub backup: bool,
+
+    /// Only sync keys matching one of these globs
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Skip keys matching any of these globs
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Steps that rename keys between reading and writing
+    #[serde(default)]
+    pub transforms: Transforms,
+
+    /// Undo `transforms` instead of applying them, for the opposite direction
+    #[serde(default)]
+    pub reverse_transforms: bool,
+
+    /// Settings for any `vault://` sources used by this preset
+    #[serde(default)]
+    pub vault: VaultConfig,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct VaultConfig {
+    /// Server address. Falls back to `VAULT_ADDR`.
+    pub address: Option<String>,
+
+    /// Vault Enterprise namespace. Falls back to `VAULT_NAMESPACE`.
+    pub namespace: Option<String>,
+
+    /// How to read nested objects and arrays
+    #[serde(default)]
+    pub nested: NestedValues,
+
+    /// Joins keys when nested values are flattened. Defaults to `_`.
+    pub separator: Option<String>,
+
+    #[serde(default)]
+    pub auth: VaultAuthConfig,
+
+    #[serde(default)]
+    pub tls: VaultTlsConfig,
+}
+
+/// TLS settings for Vault. Each falls back to the matching `VAULT_*` variable.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct VaultTlsConfig {
+    /// PEM file of CA certificates to trust (`VAULT_CACERT`)
+    pub ca_cert: Option<String>,
+
+    /// Directory of PEM CA certificates to trust (`VAULT_CAPATH`)
+    pub ca_path: Option<String>,
+
+    /// PEM client certificate for mutual TLS (`VAULT_CLIENT_CERT`)
+    pub client_cert: Option<String>,
+
+    /// PEM private key for the client certificate (`VAULT_CLIENT_KEY`)
+    pub client_key: Option<String>,
+
+    /// Disable server certificate verification (`VAULT_SKIP_VERIFY`)
+    pub skip_verify: Option<bool>,
+}
+
+/// How to obtain a Vault token. Credentials that are left out of the config
+/// file are read from environment variables instead.
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum VaultAuthConfig {
+    /// Use `VAULT_TOKEN` or `~/.vault-token`
+    #[default]
+    Token,
+
+    AppRole {
+        role_id: Option<String>,
+        secret_id: Option<String>,
+        mount: Option<String>,
+    },
+
+    Kubernetes {
+        role: String,
+        jwt_path: Option<String>,
+        mount: Option<String>,
+    },
+
+    Userpass {
+        username: String,
+        password: Option<String>,
+        mount: Option<String>,
+    },
+
+    Jwt {
+        role: String,
+        jwt_path: Option<String>,
+        mount: Option<String>,
+    },
 }
 
 impl Config {
     pub fn from_file(path: &str) -> Result<Config, ConfigError> {
-        let exists = path::Path::new(path).exists();
-
-        // If the file does not exist, return default config
-        if !exists && path == DEFAULT_CONFIG {
-            return Ok(Config::default());
-        }
+        let path = if path == DEFAULT_CONFIG {
+            // If there is no default config here or in a parent directory, return default config
+            match find_default_config() {
+                Some(path) => path,
+                None => return Ok(Config::default()),
+            }
+        } else {
+            path::PathBuf::from(path)
+        };
 
-        let file = fs::File::open(path).map_err(|source| ConfigError::OpenFile {
-            path: path.to_string(),
+        let file = fs::File::open(&path).map_err(|source| ConfigError::OpenFile {
+            path: path.display().to_string(),
             source,
         })?;
         let reader = io::BufReader::new(file);
 
-        let cfg: Config = serde_json::from_reader(reader).map_err(ConfigError::Parse)?;
+        let mut cfg: Config = serde_json::from_reader(reader).map_err(ConfigError::Parse)?;
 
         cfg.validate()?;
 
+        // Relative file paths in presets are relative to the config file
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            for preset in cfg.presets.values_mut() {
+                for uri in preset.from.iter_mut().chain(preset.to.iter_mut()) {
+                    *uri = crate::sources::rebase_uri(uri, dir);
+                }
+            }
+        }
+
         Ok(cfg)
     }
 
     fn validate(&self) -> Result<(), ConfigError> {
         for (name, preset) in &self.presets {
-            url::Url::parse(&preset.from).map_err(|source| ConfigError::InvalidPresetUrl {
-                preset: name.clone(),
-                field: "from".to_string(),
-                source,
-            })?;
-            url::Url::parse(&preset.to).map_err(|source| ConfigError::InvalidPresetUrl {
-                preset: name.clone(),
-                field: "to".to_string(),
-                source,
-            })?;
+            for (field, uris) in [("from", &preset.from), ("to", &preset.to)] {
+                if uris.is_empty() {
+                    return Err(ConfigError::EmptyPresetField {
+                        preset: name.clone(),
+                        field,
+                    });
+                }
+
+                for uri in uris {
+                    url::Url::parse(uri).map_err(|source| ConfigError::InvalidPresetUrl {
+                        preset: name.clone(),
+                        field: field.to_string(),
+                        source,
+                    })?;
+                }
+            }
         }
+
+        for (group, members) in &self.groups {
+            if self.presets.contains_key(group) {
+                return Err(ConfigError::GroupShadowsPreset(group.clone()));
+            }
+
+            if let Some(preset) = members.iter().find(|m| !self.presets.contains_key(*m)) {
+                return Err(ConfigError::UnknownGroupMember {
+                    group: group.clone(),
+                    preset: preset.clone(),
+                });
+            }
+        }
+
         Ok(())
     }
+
+    /// Expand groups into their presets, or list every preset when `all` is
+    /// set. Presets run in the order given, and only once each.
+    pub fn resolve_presets(&self, names: &[String], all: bool) -> Result<Vec<String>, ConfigError> {
+        if all {
+            if self.presets.is_empty() {
+                return Err(ConfigError::NoPresets);
+            }
+
+            let mut all_presets: Vec<String> = self.presets.keys().cloned().collect();
+            all_presets.sort();
+            return Ok(all_presets);
+        }
+
+        let mut resolved: Vec<String> = Vec::new();
+
+        for name in names {
+            let members = match self.groups.get(name) {
+                Some(members) => members.as_slice(),
+                None if self.presets.contains_key(name) => std::slice::from_ref(name),
+                None => return Err(ConfigError::PresetNotFound(name.clone())),
+            };
+
+            for member in members {
+                if !resolved.contains(member) {
+                    resolved.push(member.clone());
+                }
+            }
+        }
+
+        Ok(resolved)
+    }
+}
+
+/// Look for the default config in the working directory and then in each
+/// parent, so presets can be used from anywhere inside a project. The path
+/// is relative to the working directory.
+fn find_default_config() -> Option<path::PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+
+    cwd.ancestors()
+        .position(|dir| dir.join(DEFAULT_CONFIG).is_file())
+        .map(|depth| path::Path::new(&"../".repeat(depth)).join(DEFAULT_CONFIG))
+}
+
+/// Accept either a single string or a list of strings.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> Config {
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        cfg.validate().unwrap();
+        cfg
+    }
+
+    const PRESETS: &str = r#"{
+        "presets": {
+            "pull-api": { "from": "vault://secret/api", "to": "file://api/.env" },
+            "pull-worker": { "from": "vault://secret/worker", "to": "file://worker/.env" },
+            "push": { "from": "file://.env", "to": ["vault://secret/a", "vault://secret/b"] }
+        },
+        "groups": { "bootstrap": ["pull-worker", "pull-api"] }
+    }"#;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn single_source_or_list() {
+        let cfg = config(PRESETS);
+        assert_eq!(cfg.presets["pull-api"].to, ["file://api/.env"]);
+        assert_eq!(cfg.presets["push"].to.len(), 2);
+    }
+
+    #[test]
+    fn groups_expand_in_order_without_duplicates() {
+        let cfg = config(PRESETS);
+        let resolved = cfg
+            .resolve_presets(&names(&["bootstrap", "pull-api", "push"]), false)
+            .unwrap();
+        assert_eq!(resolved, ["pull-worker", "pull-api", "push"]);
+    }
+
+    #[test]
+    fn all_lists_every_preset() {
+        let cfg = config(PRESETS);
+        let resolved = cfg.resolve_presets(&[], true).unwrap();
+        assert_eq!(resolved, ["pull-api", "pull-worker", "push"]);
+    }
+
+    #[test]
+    fn unknown_preset_is_an_error() {
+        let cfg = config(PRESETS);
+        assert!(matches!(
+            cfg.resolve_presets(&names(&["nope"]), false),
+            Err(ConfigError::PresetNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn group_members_must_exist() {
+        let cfg: Config =
+            serde_json::from_str(r#"{ "presets": {}, "groups": { "bootstrap": ["missing"] } }"#)
+                .unwrap();
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::UnknownGroupMember { .. })
+        ));
+    }
 }
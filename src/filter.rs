@@ -0,0 +1,103 @@
+use crate::secrets::Secrets;
+
+/// Selects secrets by key with glob patterns, where `*` matches any run of
+/// characters and `?` matches exactly one.
+#[derive(Debug, Clone, Default)]
+pub struct KeyFilter {
+    /// Keep only keys matching one of these. Everything is kept when empty.
+    pub include: Vec<String>,
+
+    /// Drop keys matching any of these, even if they are included.
+    pub exclude: Vec<String>,
+}
+
+impl KeyFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pattern| glob_match(pattern, key));
+
+        included && !self.exclude.iter().any(|pattern| glob_match(pattern, key))
+    }
+
+    /// Remove every secret whose key does not match.
+    pub fn apply(&self, secrets: &mut Secrets) {
+        if !self.is_empty() {
+            secrets.content.retain(|key, _| self.matches(key));
+        }
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+
+    // Position of the last `*` and the text position it was tried at, so
+    // that it can be made to match one more character on a mismatch.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("DB_*", "DB_PASSWORD"));
+        assert!(glob_match("*_ADMIN_*", "DB_ADMIN_PASSWORD"));
+        assert!(glob_match("API_?EY", "API_KEY"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("DB_*", "APP_DB_URL"));
+        assert!(!glob_match("API_?EY", "API_KKEY"));
+        assert!(!glob_match("*_ADMIN", "ADMIN_USER"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = KeyFilter {
+            include: vec!["DB_*".to_string()],
+            exclude: vec!["*_ADMIN_*".to_string()],
+        };
+
+        assert!(filter.matches("DB_PASSWORD"));
+        assert!(!filter.matches("DB_ADMIN_PASSWORD"));
+        assert!(!filter.matches("API_KEY"));
+    }
+
+    #[test]
+    fn empty_filter_keeps_everything() {
+        assert!(KeyFilter::default().matches("ANYTHING"));
+    }
+}
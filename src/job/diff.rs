@@ -1,57 +1,200 @@
+use crate::config::{Format, Mask};
+use crate::filter::KeyFilter;
 use crate::sources::Source;
+use crate::transform::Transforms;
 use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::io::IsTerminal;
+use std::rc::Rc;
 
 const CONTEXT_LINES: usize = 3;
+const PARTIAL_MIN_LEN: usize = 8;
+
+/// Returned by a DiffJob run with `exit_code` when the secrets differ, so
+/// that the process can exit with status 1 like `git diff --exit-code`.
+#[derive(Debug, thiserror::Error)]
+#[error("secrets differ between source and target")]
+pub struct DriftDetected;
+
+/// JSON reports collected from several DiffJobs, to be printed together.
+pub type JsonReports = Rc<RefCell<Vec<serde_json::Value>>>;
+
+/// How a DiffJob reports differences.
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    pub format: Format,
+
+    /// Include SHA-256 hashes of old and new values in JSON output
+    pub hash_values: bool,
+
+    /// How values are shown in text output
+    pub mask: Mask,
+
+    /// Fail with DriftDetected when the secrets differ
+    pub exit_code: bool,
+
+    /// Which keys are compared, on both sides
+    pub filter: KeyFilter,
+
+    /// Renames applied to origin keys before comparing
+    pub transforms: Transforms,
+
+    /// Collect JSON reports here instead of printing them, so that a
+    /// recursive diff prints a single document
+    pub json_reports: Option<JsonReports>,
+}
 
 pub struct DiffJob {
     from: Box<dyn Source>,
     to: Box<dyn Source>,
     to_uri: String,
+    options: DiffOptions,
 }
 
 impl DiffJob {
-    pub fn new(from: Box<dyn Source>, to: Box<dyn Source>, to_uri: String) -> Self {
-        Self { from, to, to_uri }
+    pub fn new(
+        from: Box<dyn Source>,
+        to: Box<dyn Source>,
+        to_uri: String,
+        options: DiffOptions,
+    ) -> Self {
+        Self {
+            from,
+            to,
+            to_uri,
+            options,
+        }
+    }
+
+    fn print_text(
+        &self,
+        diff_lines: &[DiffLine],
+        layers: &BTreeMap<String, String>,
+        added: usize,
+        changed: usize,
+        removed: usize,
+    ) {
+        let hunks = build_hunks(diff_lines);
+        let printer = DiffPrinter::new(self.options.mask, layers);
+
+        printer.print_header(&self.to_uri);
+        for hunk in &hunks {
+            printer.print_hunk_header(hunk);
+            for line in &hunk.lines {
+                printer.print_line(line);
+            }
+        }
+
+        eprintln!("\n{added} added, {changed} changed, {removed} removed");
+    }
+
+    fn print_json(
+        &self,
+        diff_lines: &[DiffLine],
+        layers: &BTreeMap<String, String>,
+        added: usize,
+        changed: usize,
+        removed: usize,
+    ) -> Result<()> {
+        let changes = collect_changes(diff_lines)
+            .into_iter()
+            .map(|change| JsonChange {
+                old_hash: change
+                    .old
+                    .as_deref()
+                    .filter(|_| self.options.hash_values)
+                    .map(hash_value),
+                new_hash: change
+                    .new
+                    .as_deref()
+                    .filter(|_| self.options.hash_values)
+                    .map(hash_value),
+                layer: change
+                    .new
+                    .as_ref()
+                    .and_then(|_| layers.get(&change.key).cloned()),
+                key: change.key,
+                change: change.kind,
+            })
+            .collect();
+
+        let report = JsonReport {
+            target: &self.to_uri,
+            in_sync: added == 0 && changed == 0 && removed == 0,
+            summary: JsonSummary {
+                added,
+                changed,
+                removed,
+            },
+            changes,
+        };
+
+        if let Some(reports) = &self.options.json_reports {
+            let value = serde_json::to_value(&report).context("unable to encode diff")?;
+            reports.borrow_mut().push(value);
+            return Ok(());
+        }
+
+        let json = serde_json::to_string_pretty(&report).context("unable to encode diff")?;
+        println!("{json}");
+
+        Ok(())
     }
 }
 
 impl super::Job for DiffJob {
     fn run(&self) -> Result<()> {
-        let from_secrets = self
+        let (mut from_secrets, layers) = self
             .from
-            .read_secrets()
+            .read_secrets_with_layers()
             .context("unable to read secrets from source")?;
 
-        let to_secrets = self
+        let mut to_secrets = self
             .to
             .read_secrets()
             .context("unable to read secrets from target")?;
 
+        self.options.filter.apply(&mut from_secrets);
+        let from_secrets = self
+            .options
+            .transforms
+            .apply(from_secrets)
+            .context("unable to transform secret keys")?;
+
+        // Target keys are already transformed, so filter them by the name
+        // they would have in the origin
+        let inverse = self.options.transforms.inverse();
+        to_secrets
+            .content
+            .retain(|key, _| self.options.filter.matches(&inverse.key(key)));
+
+        let layers: BTreeMap<String, String> = layers
+            .into_iter()
+            .map(|(key, layer)| (self.options.transforms.key(&key), layer))
+            .collect();
+
         let from_map = &from_secrets.content;
         let to_map = &to_secrets.content;
 
         let (diff_lines, added, changed, removed) = build_diff_lines(from_map, to_map);
 
-        if added == 0 && changed == 0 && removed == 0 {
+        let in_sync = added == 0 && changed == 0 && removed == 0;
+
+        if self.options.format == Format::Json {
+            self.print_json(&diff_lines, &layers, added, changed, removed)?;
+        } else if in_sync {
             eprintln!("Secrets are in sync.");
-            return Ok(());
+        } else {
+            self.print_text(&diff_lines, &layers, added, changed, removed);
         }
 
-        let hunks = build_hunks(&diff_lines);
-        let printer = DiffPrinter::new();
-
-        printer.print_header(&self.to_uri);
-        for hunk in &hunks {
-            printer.print_hunk_header(hunk);
-            for line in &hunk.lines {
-                printer.print_line(line);
-            }
+        if self.options.exit_code && !in_sync {
+            return Err(DriftDetected.into());
         }
 
-        eprintln!("\n{added} added, {changed} changed, {removed} removed");
-
         Ok(())
     }
 }
@@ -65,20 +208,158 @@ fn format_entry(key: &str, value: &str) -> String {
     format!("{key}={escaped}")
 }
 
+/// A single secret shown in a diff.
 #[derive(Clone, Debug)]
-enum DiffLine {
+pub(super) struct Entry {
+    pub key: String,
+    pub value: String,
+}
+
+impl Entry {
+    fn new(key: &str, value: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl Entry {
+    /// Format the entry with its value hidden according to `mask`.
+    fn masked(&self, mask: Mask) -> String {
+        match mask {
+            Mask::None => format_entry(&self.key, &self.value),
+            Mask::Redact => format!("{}=***", self.key),
+            Mask::Partial => format!("{}={}", self.key, partial_value(&self.value)),
+            Mask::Hash => format!("{}=<{}>", self.key, &hash_value(&self.value)[..15]),
+        }
+    }
+}
+
+/// Reveal the first and last two characters of a value. Values shorter than
+/// PARTIAL_MIN_LEN are fully redacted since the ends would give most of it away.
+fn partial_value(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() < PARTIAL_MIN_LEN {
+        return "***".to_string();
+    }
+
+    let head: String = chars[..2].iter().collect();
+    let tail: String = chars[chars.len() - 2..].iter().collect();
+    format!("{head}***{tail}")
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format_entry(&self.key, &self.value))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) enum DiffLine {
     /// Unchanged line surrounding a change
-    Context(String),
+    Context(Entry),
 
     /// Line that should be removed from the target
-    Remove(String),
+    Remove(Entry),
 
     /// Line that should be added to the target
-    Add(String),
+    Add(Entry),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ChangeKind {
+    Added,
+    Changed,
+    Removed,
+}
+
+/// A key that differs between the origin and the target.
+#[derive(Clone, Debug)]
+pub(super) struct Change {
+    pub key: String,
+    pub kind: ChangeKind,
+
+    /// The value in the target, if any
+    pub old: Option<String>,
+
+    /// The value in the origin, if any
+    pub new: Option<String>,
+}
+
+/// Collapse diff lines into one change per key. A removal directly followed
+/// by an addition of the same key is a changed value.
+pub(super) fn collect_changes(diff_lines: &[DiffLine]) -> Vec<Change> {
+    let mut changes: Vec<Change> = Vec::new();
+
+    for line in diff_lines {
+        match line {
+            DiffLine::Context(_) => {}
+            DiffLine::Remove(entry) => changes.push(Change {
+                key: entry.key.clone(),
+                kind: ChangeKind::Removed,
+                old: Some(entry.value.clone()),
+                new: None,
+            }),
+            DiffLine::Add(entry) => match changes.last_mut() {
+                Some(last) if last.key == entry.key && last.kind == ChangeKind::Removed => {
+                    last.kind = ChangeKind::Changed;
+                    last.new = Some(entry.value.clone());
+                }
+                _ => changes.push(Change {
+                    key: entry.key.clone(),
+                    kind: ChangeKind::Added,
+                    old: None,
+                    new: Some(entry.value.clone()),
+                }),
+            },
+        }
+    }
+
+    changes
+}
+
+/// Fingerprint a value so changes can be tracked without revealing it.
+fn hash_value(value: &str) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("sha256:{hex}")
+}
+
+/// The machine-readable form of a diff, printed with `--format json`.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    target: &'a str,
+    in_sync: bool,
+    summary: JsonSummary,
+    changes: Vec<JsonChange>,
+}
+
+#[derive(Serialize)]
+struct JsonSummary {
+    added: usize,
+    changed: usize,
+    removed: usize,
+}
+
+#[derive(Serialize)]
+struct JsonChange {
+    key: String,
+    change: ChangeKind,
+
+    /// The layered source the new value comes from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layer: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_hash: Option<String>,
 }
 
 /// Build a list of DiffLine representing the differences between from_map and to_map.
-fn build_diff_lines(
+pub(super) fn build_diff_lines(
     from_map: &BTreeMap<String, String>,
     to_map: &BTreeMap<String, String>,
 ) -> (Vec<DiffLine>, usize, usize, usize) {
@@ -94,20 +375,20 @@ fn build_diff_lines(
     for key in &all_keys {
         match (from_map.get(*key), to_map.get(*key)) {
             (Some(from_val), None) => {
-                diff_lines.push(DiffLine::Add(format_entry(key, from_val)));
+                diff_lines.push(DiffLine::Add(Entry::new(key, from_val)));
                 added += 1;
             }
             (None, Some(to_val)) => {
-                diff_lines.push(DiffLine::Remove(format_entry(key, to_val)));
+                diff_lines.push(DiffLine::Remove(Entry::new(key, to_val)));
                 removed += 1;
             }
             (Some(from_val), Some(to_val)) if from_val != to_val => {
-                diff_lines.push(DiffLine::Remove(format_entry(key, to_val)));
-                diff_lines.push(DiffLine::Add(format_entry(key, from_val)));
+                diff_lines.push(DiffLine::Remove(Entry::new(key, to_val)));
+                diff_lines.push(DiffLine::Add(Entry::new(key, from_val)));
                 changed += 1;
             }
             (Some(from_val), Some(_)) => {
-                diff_lines.push(DiffLine::Context(format_entry(key, from_val)));
+                diff_lines.push(DiffLine::Context(Entry::new(key, from_val)));
             }
             _ => {}
         }
@@ -208,14 +489,20 @@ fn build_hunks(diff_lines: &[DiffLine]) -> Vec<Hunk> {
 }
 
 /// Helper for printing diffs with optional color support.
-struct DiffPrinter {
+struct DiffPrinter<'a> {
     use_color: bool,
+    mask: Mask,
+
+    /// The layer each origin key came from, when the origin is layered
+    layers: &'a BTreeMap<String, String>,
 }
 
-impl DiffPrinter {
-    fn new() -> Self {
+impl<'a> DiffPrinter<'a> {
+    fn new(mask: Mask, layers: &'a BTreeMap<String, String>) -> Self {
         Self {
             use_color: std::io::stdout().is_terminal(),
+            mask,
+            layers,
         }
     }
 
@@ -251,17 +538,29 @@ impl DiffPrinter {
         }
     }
 
+    /// Name the layer an origin value came from, e.g. `  # from file://.env.local`.
+    fn layer_note(&self, entry: &Entry) -> String {
+        self.layers
+            .get(&entry.key)
+            .map(|layer| format!("  # from {layer}"))
+            .unwrap_or_default()
+    }
+
     fn print_line(&self, line: &DiffLine) {
         match line {
-            DiffLine::Context(text) => println!(" {text}"),
-            DiffLine::Remove(text) => {
+            DiffLine::Context(entry) => {
+                println!(" {}{}", entry.masked(self.mask), self.layer_note(entry))
+            }
+            DiffLine::Remove(entry) => {
+                let text = entry.masked(self.mask);
                 if self.use_color {
                     println!("\x1b[31m-{text}\x1b[0m");
                 } else {
                     println!("-{text}");
                 }
             }
-            DiffLine::Add(text) => {
+            DiffLine::Add(entry) => {
+                let text = format!("{}{}", entry.masked(self.mask), self.layer_note(entry));
                 if self.use_color {
                     println!("\x1b[32m+{text}\x1b[0m");
                 } else {
@@ -309,7 +608,7 @@ mod tests {
         assert!(hunks[0]
             .lines
             .iter()
-            .any(|l| matches!(l, DiffLine::Add(s) if s.contains("FOO"))));
+            .any(|l| matches!(l, DiffLine::Add(s) if s.to_string().contains("FOO"))));
     }
 
     #[test]
@@ -325,7 +624,7 @@ mod tests {
         assert!(hunks[0]
             .lines
             .iter()
-            .any(|l| matches!(l, DiffLine::Remove(s) if s.contains("FOO"))));
+            .any(|l| matches!(l, DiffLine::Remove(s) if s.to_string().contains("FOO"))));
     }
 
     #[test]
@@ -364,7 +663,8 @@ mod tests {
             .iter()
             .find(|l| matches!(l, DiffLine::Add(_)))
             .unwrap();
-        if let DiffLine::Add(text) = add_line {
+        if let DiffLine::Add(entry) = add_line {
+            let text = entry.to_string();
             assert!(
                 text.contains(r#"\"world\""#),
                 "Value should be JSON-escaped"
@@ -478,6 +778,46 @@ mod tests {
         assert_eq!(new_count, 3); // A (ctx) + B new (add) + C (ctx)
     }
 
+    #[test]
+    fn changes_are_collected_per_key() {
+        let from = make_secrets(&[("ADDED", "1"), ("CHANGED", "new"), ("SAME", "v")]);
+        let to = make_secrets(&[("CHANGED", "old"), ("REMOVED", "1"), ("SAME", "v")]);
+        let (diff_lines, _, _, _) = build_diff_lines(&from.content, &to.content);
+        let changes = collect_changes(&diff_lines);
+
+        let summary: Vec<(&str, ChangeKind)> =
+            changes.iter().map(|c| (c.key.as_str(), c.kind)).collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("ADDED", ChangeKind::Added),
+                ("CHANGED", ChangeKind::Changed),
+                ("REMOVED", ChangeKind::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_values_keep_old_and_new() {
+        let from = make_secrets(&[("FOO", "new")]);
+        let to = make_secrets(&[("FOO", "old")]);
+        let (diff_lines, _, _, _) = build_diff_lines(&from.content, &to.content);
+        let changes = collect_changes(&diff_lines);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].old.as_deref(), Some("old"));
+        assert_eq!(changes[0].new.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn hash_value_is_sha256() {
+        assert_eq!(
+            hash_value("secret"),
+            "sha256:2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        );
+    }
+
     #[test]
     fn dollar_signs_are_escaped_in_diff() {
         let from = make_secrets(&[("SECRET", "p@$$word")]);
@@ -489,7 +829,8 @@ mod tests {
             .iter()
             .find(|l| matches!(l, DiffLine::Add(_)))
             .unwrap();
-        if let DiffLine::Add(text) = add_line {
+        if let DiffLine::Add(entry) = add_line {
+            let text = entry.to_string();
             assert!(
                 text.contains(r#"\$"#),
                 "Dollar signs should be escaped in diff output; got: {text}"
@@ -500,4 +841,27 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn masked_entries_hide_values() {
+        let entry = Entry::new("API_KEY", "abcdefghij");
+
+        assert_eq!(entry.masked(Mask::None), r#"API_KEY="abcdefghij""#);
+        assert_eq!(entry.masked(Mask::Redact), "API_KEY=***");
+        assert_eq!(entry.masked(Mask::Partial), "API_KEY=ab***ij");
+        assert_eq!(entry.masked(Mask::Hash), "API_KEY=<sha256:72399361>");
+    }
+
+    #[test]
+    fn partial_mask_redacts_short_values() {
+        assert_eq!(partial_value("short"), "***");
+        assert_eq!(partial_value("ünïcødé!"), "ün***é!");
+    }
+
+    #[test]
+    fn hash_mask_distinguishes_changed_values() {
+        let old = Entry::new("TOKEN", "old");
+        let new = Entry::new("TOKEN", "new");
+        assert_ne!(old.masked(Mask::Hash), new.masked(Mask::Hash));
+    }
 }
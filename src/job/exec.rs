@@ -0,0 +1,173 @@
+use super::Job;
+use crate::filter::KeyFilter;
+use crate::sources::Source;
+use crate::transform::Transforms;
+use anyhow::{Context, Result};
+use std::process::ExitStatus;
+use tokio::process::{Child, Command};
+
+/// Returned by an ExecJob when the child exits unsuccessfully, so that the
+/// process can exit with the same status.
+#[derive(Debug, thiserror::Error)]
+#[error("command exited with status {0}")]
+pub struct ChildExited(pub i32);
+
+/// Runs a command with the secrets from a source added to its environment.
+/// The secrets are only ever held in memory and in the child's environment.
+/// They are filtered and transformed the same way a sync would.
+pub struct ExecJob {
+    origin: Box<dyn Source>,
+    command: Vec<String>,
+    filter: KeyFilter,
+    transforms: Transforms,
+}
+
+impl ExecJob {
+    pub fn new(
+        origin: Box<dyn Source>,
+        command: Vec<String>,
+        filter: KeyFilter,
+        transforms: Transforms,
+    ) -> Self {
+        Self {
+            origin,
+            command,
+            filter,
+            transforms,
+        }
+    }
+}
+
+impl Job for ExecJob {
+    /// Start the command, forward signals to it until it exits and report
+    /// its exit status.
+    fn run(&self) -> Result<()> {
+        let mut secrets = self
+            .origin
+            .read_secrets()
+            .context("unable to read secrets from source")?;
+        self.filter.apply(&mut secrets);
+        let secrets = self
+            .transforms
+            .apply(secrets)
+            .context("unable to transform secret keys")?;
+
+        let (program, args) = self.command.split_first().context("no command to run")?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build Tokio runtime")?;
+
+        let status = runtime.block_on(async {
+            let mut child = Command::new(program)
+                .args(args)
+                .envs(&secrets.content)
+                .spawn()
+                .with_context(|| format!("unable to start '{program}'"))?;
+
+            wait_forwarding_signals(&mut child).await
+        })?;
+
+        match exit_code(status) {
+            0 => Ok(()),
+            code => Err(ChildExited(code).into()),
+        }
+    }
+}
+
+/// Wait for the child, passing on any termination or user signal we receive.
+/// Ctrl-C already reaches the child through the terminal's process group, so
+/// SIGINT is only kept from stopping us before the child exits.
+#[cfg(unix)]
+async fn wait_forwarding_signals(child: &mut Child) -> Result<ExitStatus> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut quit = signal(SignalKind::quit())?;
+    let mut user1 = signal(SignalKind::user_defined1())?;
+    let mut user2 = signal(SignalKind::user_defined2())?;
+
+    loop {
+        let received = tokio::select! {
+            status = child.wait() => return status.context("unable to wait for command"),
+            _ = interrupt.recv() => continue,
+            _ = terminate.recv() => libc::SIGTERM,
+            _ = hangup.recv() => libc::SIGHUP,
+            _ = quit.recv() => libc::SIGQUIT,
+            _ = user1.recv() => libc::SIGUSR1,
+            _ = user2.recv() => libc::SIGUSR2,
+        };
+
+        if let Some(pid) = child.id() {
+            // SAFETY: kill has no memory safety requirements; the pid belongs
+            // to a child we have not reaped yet.
+            unsafe {
+                libc::kill(pid as libc::pid_t, received);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_forwarding_signals(child: &mut Child) -> Result<ExitStatus> {
+    child.wait().await.context("unable to wait for command")
+}
+
+/// The shell convention for a child's exit status: its exit code, or 128
+/// plus the signal number when it was killed by a signal.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::sources::test_source::MemorySource;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn exit_code_passes_through() {
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), 0);
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+    }
+
+    #[test]
+    fn killed_by_signal_is_128_plus_signal() {
+        assert_eq!(exit_code(ExitStatus::from_raw(libc::SIGTERM)), 143);
+    }
+
+    #[test]
+    fn only_filtered_and_transformed_secrets_reach_the_command() {
+        let origin = MemorySource::new(&[("DB_PASS", "secret"), ("OTHER", "x")]);
+        let transforms: Transforms = serde_json::from_str(r#"[{"add_prefix": "APP_"}]"#).unwrap();
+        let job = ExecJob::new(
+            Box::new(origin),
+            [
+                "sh",
+                "-c",
+                r#"test "$APP_DB_PASS" = secret && test -z "$APP_OTHER$OTHER""#,
+            ]
+            .map(String::from)
+            .to_vec(),
+            KeyFilter {
+                include: vec!["DB_*".into()],
+                exclude: vec![],
+            },
+            transforms,
+        );
+
+        job.run().unwrap();
+    }
+}
@@ -1,65 +1,261 @@
-use crate::sources::{Source, SourceCreateError};
+use crate::config::{Args, Config, ConfigError, Format, Mask, PresetConfig};
+use crate::filter::KeyFilter;
+use crate::sources::{Source, SourceCreateError, SourceOptions};
+use crate::transform::Transforms;
 use anyhow::Result;
 use std::io::IsTerminal;
 
 mod diff;
+mod exec;
 mod init;
+mod presets;
+mod recursive;
 mod sync;
 
+pub use diff::DriftDetected;
+pub use exec::ChildExited;
+
 pub trait Job {
     fn run(&self) -> Result<()>;
 }
 
-pub fn new_job(
-    config: &crate::config::Config,
-    from: Option<String>,
-    to: Option<String>,
-    preset: Option<String>,
-    diff: bool,
-) -> Result<Box<dyn Job>> {
-    if preset == Some("init".to_string()) {
+pub fn new_job(config: &Config, args: &Args) -> Result<Box<dyn Job>> {
+    if args.subcommand() == Some("init") {
         return Ok(Box::new(init::InitJob {}));
     }
 
-    let preset_cfg = preset.as_ref().and_then(|p| config.presets.get(p));
+    let presets = config.resolve_presets(args.preset_names(), args.all)?;
+
+    if args.subcommand() == Some("run") {
+        let preset_cfg = presets.first().and_then(|p| config.presets.get(p));
+        return exec_job(args, preset_cfg);
+    }
+
+    match presets.as_slice() {
+        [] => preset_job(args, None, true, None),
+        [name] => preset_job(args, config.presets.get(name), true, None),
+        // Several presets can't share stdin and stdout
+        _ => {
+            // Their JSON diffs are printed together, as a single document
+            let reports =
+                (args.diff && args.format == Format::Json).then(diff::JsonReports::default);
+
+            let jobs = presets
+                .iter()
+                .map(|name| {
+                    (
+                        name.clone(),
+                        preset_job(args, config.presets.get(name), false, reports.as_ref()),
+                    )
+                })
+                .collect();
+
+            let job = presets::PresetsJob::new(jobs);
+            Ok(Box::new(match reports {
+                Some(reports) => job.print_json_reports(reports),
+                None => job,
+            }))
+        }
+    }
+}
+
+fn source_options(preset_cfg: Option<&PresetConfig>) -> SourceOptions {
+    SourceOptions {
+        vault: preset_cfg.map(|p| p.vault.clone()).unwrap_or_default(),
+        format: None,
+        backup: false,
+    }
+}
+
+fn exec_job(args: &Args, preset_cfg: Option<&PresetConfig>) -> Result<Box<dyn Job>> {
+    let from = Some(args.from.clone())
+        .filter(|from| !from.is_empty())
+        .or_else(|| preset_cfg.map(|p| p.from.clone()))
+        .ok_or(SourceCreateError::NoSourceProvided { field: "from" })?;
+    let origin = <dyn Source>::layered(&from, &source_options(preset_cfg))?;
+
+    Ok(Box::new(exec::ExecJob::new(
+        origin,
+        args.command.clone(),
+        key_filter(args, preset_cfg),
+        preset_transforms(preset_cfg),
+    )))
+}
+
+/// The keys to sync: patterns from the command line add to the preset's.
+fn key_filter(args: &Args, preset_cfg: Option<&PresetConfig>) -> KeyFilter {
+    let mut filter = KeyFilter {
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+    };
+    if let Some(preset) = preset_cfg {
+        filter.include.extend(preset.include.iter().cloned());
+        filter.exclude.extend(preset.exclude.iter().cloned());
+    }
+
+    filter
+}
+
+/// The preset's key transforms, undone when it syncs in reverse.
+fn preset_transforms(preset_cfg: Option<&PresetConfig>) -> Transforms {
+    preset_cfg
+        .map(|p| {
+            if p.reverse_transforms {
+                p.transforms.inverse()
+            } else {
+                p.transforms.clone()
+            }
+        })
+        .unwrap_or_default()
+}
+
+/// Build the job for a single preset, or for --from and --to alone. JSON
+/// diffs are added to `shared_reports` when given, for the caller to print.
+fn preset_job(
+    args: &Args,
+    preset_cfg: Option<&PresetConfig>,
+    use_std: bool,
+    shared_reports: Option<&diff::JsonReports>,
+) -> Result<Box<dyn Job>> {
+    let options = source_options(preset_cfg);
+
+    let recursive = args.recursive || preset_cfg.is_some_and(|p| p.recursive);
+
+    let filter = key_filter(args, preset_cfg);
+    let transforms = preset_transforms(preset_cfg);
+    let sync_options = sync::SyncOptions {
+        merge: args.merge || preset_cfg.is_some_and(|p| p.merge),
+        dry_run: args.dry_run,
+        filter: filter.clone(),
+        transforms: transforms.clone(),
+        reverse_transforms: preset_cfg.is_some_and(|p| p.reverse_transforms),
+    };
+
+    if args.diff {
+        // A recursive diff prints its own reports, unless they are shared
+        let own_reports = (shared_reports.is_none() && recursive && args.format == Format::Json)
+            .then(diff::JsonReports::default);
+
+        let diff_options = diff::DiffOptions {
+            format: args.format,
+            hash_values: args.hash_values,
+            mask: args.mask.unwrap_or(if std::io::stdout().is_terminal() {
+                Mask::None
+            } else {
+                Mask::Redact
+            }),
+            exit_code: args.exit_code,
+            filter,
+            transforms,
+            json_reports: shared_reports.cloned().or_else(|| own_reports.clone()),
+        };
 
-    if diff {
         // In diff mode we never read from stdin or write to stdout automatically.
         // Both `from` and `to` are required (enforced by Args::validate).
-        let from_uri = from
+        let from_uris = Some(args.from.clone())
+            .filter(|from| !from.is_empty())
             .or_else(|| preset_cfg.map(|p| p.from.clone()))
             .ok_or(SourceCreateError::NoSourceProvided { field: "from" })?;
-        let from_source = <dyn Source>::new(&from_uri)?;
 
-        let to_uri = to
+        let to_uris = Some(args.to.clone())
+            .filter(|to| !to.is_empty())
             .or_else(|| preset_cfg.map(|p| p.to.clone()))
             .ok_or(SourceCreateError::NoSourceProvided { field: "to" })?;
-        let to_source = <dyn Source>::new(&to_uri)?;
+        let to_uri = single_uri(to_uris, "--diff", "to")?;
 
-        return Ok(Box::new(diff::DiffJob::new(from_source, to_source, to_uri)));
-    }
+        if recursive {
+            let job = recursive::RecursiveJob::new(
+                single_uri(from_uris, "--recursive", "from")?,
+                to_uri,
+                options.clone(),
+                options,
+                Box::new(move |from, to, to_uri| {
+                    Box::new(diff::DiffJob::new(from, to, to_uri, diff_options.clone()))
+                }),
+            );
 
-    let from = if std::io::stdin().is_terminal() {
-        from
-    } else {
-        Some("std://".to_string())
-    };
+            return Ok(Box::new(match own_reports {
+                Some(reports) => job.print_json_reports(reports),
+                None => job,
+            }));
+        }
+
+        let from_source = <dyn Source>::layered(&from_uris, &options)?;
+        let to_source = <dyn Source>::new(&to_uri, &options)?;
+
+        return Ok(Box::new(diff::DiffJob::new(
+            from_source,
+            to_source,
+            to_uri,
+            diff_options,
+        )));
+    }
 
-    let from = from
+    // Piped stdin/stdout stand in for a preset's source and target, but
+    // never for an explicit --from or --to.
+    let from = Some(args.from.clone())
+        .filter(|from| !from.is_empty())
+        .or_else(|| {
+            (use_std && !std::io::stdin().is_terminal()).then(|| vec!["std://".to_string()])
+        })
         .or_else(|| preset_cfg.map(|p| p.from.clone()))
         .ok_or(SourceCreateError::NoSourceProvided { field: "from" })?;
-    let from = <dyn Source>::new(&from)?;
 
-    let to = if std::io::stdout().is_terminal() {
-        to
-    } else {
-        Some("std://".to_string())
-    };
-
-    let to = to
+    let to = Some(args.to.clone())
+        .filter(|to| !to.is_empty())
+        .or_else(|| {
+            (use_std && !std::io::stdout().is_terminal()).then(|| vec!["std://".to_string()])
+        })
         .or_else(|| preset_cfg.map(|p| p.to.clone()))
         .ok_or(SourceCreateError::NoSourceProvided { field: "to" })?;
-    let to = <dyn Source>::new(&to)?;
 
-    Ok(Box::new(sync::SyncJob::new(from, to)))
+    // Only targets are written in the --format or backed up
+    let target_options = SourceOptions {
+        format: args.format.target_format(),
+        backup: args.backup || preset_cfg.is_some_and(|p| p.backup),
+        ..options.clone()
+    };
+
+    if recursive {
+        return Ok(Box::new(recursive::RecursiveJob::new(
+            single_uri(from, "--recursive", "from")?,
+            single_uri(to, "--recursive", "to")?,
+            options,
+            target_options,
+            Box::new(move |from, to, to_uri| {
+                Box::new(sync::SyncJob::new(
+                    from,
+                    vec![(to_uri, Ok(to))],
+                    sync_options.clone(),
+                ))
+            }),
+        )));
+    }
+
+    let from = <dyn Source>::layered(&from, &options)?;
+
+    // A target that can't be built only fails on its own, like one that
+    // can't be written
+    let targets = to
+        .into_iter()
+        .map(|uri| {
+            let target = <dyn Source>::new(&uri, &target_options).map_err(Into::into);
+            (uri, target)
+        })
+        .collect();
+
+    Ok(Box::new(sync::SyncJob::new(from, targets, sync_options)))
+}
+
+/// Recursive jobs walk a single origin and target, and diffs compare a
+/// single target, so neither can be given several sources.
+fn single_uri(
+    uris: Vec<String>,
+    mode: &'static str,
+    field: &'static str,
+) -> Result<String, ConfigError> {
+    match <[String; 1]>::try_from(uris) {
+        Ok([uri]) => Ok(uri),
+        Err(_) => Err(ConfigError::SingleSourceOnly { mode, field }),
+    }
 }
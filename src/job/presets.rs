@@ -0,0 +1,137 @@
+use super::diff::{DriftDetected, JsonReports};
+use super::Job;
+use anyhow::{bail, Context, Result};
+
+/// How a single preset's job went.
+enum Outcome {
+    Succeeded,
+    Drifted,
+    Failed(String),
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Succeeded => f.write_str("ok"),
+            Outcome::Drifted => f.write_str("secrets differ"),
+            Outcome::Failed(err) => write!(f, "failed: {err}"),
+        }
+    }
+}
+
+/// Runs the jobs for several presets in order, then summarizes how each one
+/// went. A failing preset does not stop the ones after it.
+pub struct PresetsJob {
+    jobs: Vec<(String, Result<Box<dyn Job>>)>,
+    json_reports: Option<JsonReports>,
+}
+
+impl PresetsJob {
+    pub fn new(jobs: Vec<(String, Result<Box<dyn Job>>)>) -> Self {
+        Self {
+            jobs,
+            json_reports: None,
+        }
+    }
+
+    /// Print the JSON reports the jobs collect as one array once every
+    /// preset is done.
+    pub fn print_json_reports(mut self, reports: JsonReports) -> Self {
+        self.json_reports = Some(reports);
+        self
+    }
+}
+
+impl Job for PresetsJob {
+    fn run(&self) -> Result<()> {
+        let mut outcomes = Vec::with_capacity(self.jobs.len());
+
+        for (name, job) in &self.jobs {
+            eprintln!("==> {name}");
+
+            let outcome = match job {
+                Ok(job) => match job.run() {
+                    Ok(()) => Outcome::Succeeded,
+                    Err(err) if err.is::<DriftDetected>() => Outcome::Drifted,
+                    Err(err) => Outcome::Failed(format!("{err:#}")),
+                },
+                Err(err) => Outcome::Failed(format!("could not build job: {err:#}")),
+            };
+
+            eprintln!();
+            outcomes.push((name, outcome));
+        }
+
+        if let Some(reports) = &self.json_reports {
+            let json = serde_json::to_string_pretty(&*reports.borrow())
+                .context("unable to encode diff")?;
+            println!("{json}");
+        }
+
+        eprintln!("Summary:");
+        for (name, outcome) in &outcomes {
+            eprintln!("  {name}: {outcome}");
+        }
+
+        let total = outcomes.len();
+        let failed = outcomes
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Failed(_)))
+            .count();
+        let drifted = outcomes
+            .iter()
+            .any(|(_, outcome)| matches!(outcome, Outcome::Drifted));
+
+        eprintln!("\n{} of {total} presets succeeded", total - failed);
+
+        if failed > 0 {
+            bail!("{failed} of {total} presets failed");
+        }
+
+        if drifted {
+            return Err(DriftDetected.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Format;
+    use crate::job::diff::{DiffJob, DiffOptions};
+    use crate::sources::test_source::MemorySource;
+
+    #[test]
+    fn json_diffs_of_every_preset_are_collected_together() {
+        let reports = JsonReports::default();
+        let diff = |target: &str| -> Result<Box<dyn Job>> {
+            Ok(Box::new(DiffJob::new(
+                Box::new(MemorySource::new(&[("A", "1")])),
+                Box::new(MemorySource::default()),
+                target.to_string(),
+                DiffOptions {
+                    format: Format::Json,
+                    json_reports: Some(reports.clone()),
+                    ..Default::default()
+                },
+            )))
+        };
+
+        PresetsJob::new(vec![
+            ("api".to_string(), diff("memory://api")),
+            ("worker".to_string(), diff("memory://worker")),
+        ])
+        .print_json_reports(reports.clone())
+        .run()
+        .unwrap();
+
+        let targets: Vec<_> = reports
+            .borrow()
+            .iter()
+            .map(|report| report["target"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(targets, ["memory://api", "memory://worker"]);
+    }
+}
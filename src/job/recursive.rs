@@ -0,0 +1,218 @@
+use super::diff::{DriftDetected, JsonReports};
+use super::Job;
+use crate::sources::{join_path_uri, Source, SourceOptions};
+use anyhow::{bail, Context, Result};
+use url::Url;
+
+/// Builds the job to run for a single secret from its origin, target and target URI.
+pub type LeafJobFactory = Box<dyn Fn(Box<dyn Source>, Box<dyn Source>, String) -> Box<dyn Job>>;
+
+/// Runs a job for every secret below the origin path, pairing each one with
+/// a target at the same relative path.
+pub struct RecursiveJob {
+    from_uri: String,
+    to_uri: String,
+    options: SourceOptions,
+    to_options: SourceOptions,
+    leaf_job: LeafJobFactory,
+    json_reports: Option<JsonReports>,
+}
+
+impl RecursiveJob {
+    pub fn new(
+        from_uri: String,
+        to_uri: String,
+        options: SourceOptions,
+        to_options: SourceOptions,
+        leaf_job: LeafJobFactory,
+    ) -> Self {
+        Self {
+            from_uri,
+            to_uri,
+            options,
+            to_options,
+            leaf_job,
+            json_reports: None,
+        }
+    }
+
+    /// Print the JSON reports the leaf jobs collect as one array once every
+    /// secret is done.
+    pub fn print_json_reports(mut self, reports: JsonReports) -> Self {
+        self.json_reports = Some(reports);
+        self
+    }
+
+    /// Run the job for one secret. Sources are derived from the origin and
+    /// target when they support it, and built from a joined URI otherwise.
+    fn run_leaf(&self, origin: &dyn Source, target: Option<&dyn Source>, leaf: &str) -> Result<()> {
+        let to_uri = join_uri(&self.to_uri, leaf)?;
+
+        let from = match origin.child(leaf) {
+            Some(from) => from,
+            None => <dyn Source>::new(&join_uri(&self.from_uri, leaf)?, &self.options)?,
+        };
+        let to = match target.and_then(|target| target.child(leaf)) {
+            Some(to) => to,
+            None => <dyn Source>::new(&to_uri, &self.to_options)?,
+        };
+
+        (self.leaf_job)(from, to, to_uri).run()
+    }
+}
+
+impl Job for RecursiveJob {
+    /// Run the leaf job for every secret, reporting failures at the end
+    /// instead of stopping at the first one.
+    fn run(&self) -> Result<()> {
+        let origin = <dyn Source>::new(&self.from_uri, &self.options)?;
+        let leaves = origin
+            .list_secrets()
+            .context("unable to list secrets from source")?;
+
+        if leaves.is_empty() {
+            eprintln!("No secrets found.");
+            return Ok(());
+        }
+
+        // Vault targets are built once so that every secret shares one login.
+        // Other targets are cheap to build, and a Kubernetes or file target
+        // can't be built for a folder at all.
+        let target = if self.to_uri.starts_with("vault://") {
+            Some(<dyn Source>::new(&self.to_uri, &self.to_options)?)
+        } else {
+            None
+        };
+
+        let mut failed = 0usize;
+        let mut drifted = 0usize;
+
+        for leaf in &leaves {
+            match self.run_leaf(origin.as_ref(), target.as_deref(), leaf) {
+                Ok(()) => {}
+                Err(err) if err.is::<DriftDetected>() => drifted += 1,
+                Err(err) => {
+                    eprintln!("Failed on secret '{leaf}': {err:#}");
+                    failed += 1;
+                }
+            }
+        }
+
+        if let Some(reports) = &self.json_reports {
+            let json = serde_json::to_string_pretty(&*reports.borrow())
+                .context("unable to encode diff")?;
+            println!("{json}");
+        }
+
+        let total = leaves.len();
+        eprintln!("\n{} of {total} secrets succeeded", total - failed);
+
+        if failed > 0 {
+            bail!("{failed} of {total} secrets failed");
+        }
+
+        if drifted > 0 {
+            return Err(DriftDetected.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Append a relative secret path to a source URI, keeping its query string.
+/// Only Vault can nest secrets, so for other targets the path is flattened
+/// with `-`, e.g. `app/db` becomes the Kubernetes secret `app-db`.
+fn join_uri(uri: &str, leaf: &str) -> Result<String, url::ParseError> {
+    // File paths are joined as written, since parsing them as URLs would
+    // treat the first segment as a host
+    if let Some(joined) = join_path_uri(uri, &leaf.replace('/', "-")) {
+        return Ok(joined);
+    }
+
+    let mut url = Url::parse(uri)?;
+
+    let leaf = if url.scheme() == "vault" {
+        leaf.to_string()
+    } else {
+        leaf.replace('/', "-")
+    };
+
+    let path = format!("{}/{}", url.path().trim_end_matches('/'), leaf);
+    url.set_path(&path);
+
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Format;
+    use crate::job::diff::{DiffJob, DiffOptions};
+    use crate::sources::test_source::MemorySource;
+
+    #[test]
+    fn json_diffs_are_collected_for_one_array() {
+        let reports = JsonReports::default();
+        let options = DiffOptions {
+            format: Format::Json,
+            json_reports: Some(reports.clone()),
+            ..Default::default()
+        };
+
+        let job = RecursiveJob::new(
+            "vault://secret/apps".to_string(),
+            "file://out".to_string(),
+            SourceOptions::default(),
+            SourceOptions::default(),
+            Box::new(move |from, _to, to_uri| {
+                let to = Box::new(MemorySource::new(&[("API_KEY", "x")]));
+                Box::new(DiffJob::new(from, to, to_uri, options.clone()))
+            }),
+        )
+        .print_json_reports(reports.clone());
+
+        let origin = MemorySource::default();
+        for leaf in ["api", "db"] {
+            job.run_leaf(&origin, None, leaf).unwrap();
+        }
+
+        let reports = reports.borrow();
+        let targets: Vec<_> = reports
+            .iter()
+            .map(|report| report["target"].as_str().unwrap())
+            .collect();
+        assert_eq!(targets, ["file://out/api", "file://out/db"]);
+        assert_eq!(reports[0]["summary"]["added"], 1);
+        assert_eq!(reports[0]["summary"]["removed"], 1);
+    }
+
+    #[test]
+    fn join_vault_keeps_nesting() {
+        let uri = join_uri("vault://secret/team/app/", "nested/db").unwrap();
+        assert_eq!(uri, "vault://secret/team/app/nested/db");
+    }
+
+    #[test]
+    fn join_keeps_query() {
+        let uri = join_uri("vault://other/apps?version=2", "db").unwrap();
+        assert_eq!(uri, "vault://other/apps/db?version=2");
+    }
+
+    #[test]
+    fn join_k8s_flattens_nesting() {
+        let uri = join_uri("k8s://my-context/", "nested/db").unwrap();
+        assert_eq!(uri, "k8s://my-context/nested-db");
+    }
+
+    #[test]
+    fn join_relative_file_path() {
+        let uri = join_uri("file://../out", "nested/db").unwrap();
+        assert_eq!(uri, "file://../out/nested-db");
+    }
+
+    #[test]
+    fn join_empty_path() {
+        let uri = join_uri("vault://secret", "db").unwrap();
+        assert_eq!(uri, "vault://secret/db");
+    }
+}
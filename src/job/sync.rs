@@ -1,29 +1,314 @@
+use super::diff::{build_diff_lines, collect_changes, ChangeKind};
+use crate::filter::KeyFilter;
+use crate::secrets::Secrets;
 use crate::sources::Source;
-use anyhow::{Context, Result};
+use crate::transform::Transforms;
+use anyhow::{anyhow, bail, Context, Result};
 
+/// How a SyncJob writes to its target.
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Keep keys that only exist in the target
+    pub merge: bool,
+
+    /// Only report what would be written
+    pub dry_run: bool,
+
+    /// Which keys from the origin are written
+    pub filter: KeyFilter,
+
+    /// Renames applied to origin keys before writing
+    pub transforms: Transforms,
+
+    /// The transforms undo the ones that named the target's keys, so each
+    /// of those keys must come back unchanged
+    pub reverse_transforms: bool,
+}
+
+/// A target URI and its source, or the error building it.
+pub type Target = (String, Result<Box<dyn Source>>);
+
+/// Writes the secrets from one origin to each of its targets.
 pub struct SyncJob {
     origin: Box<dyn Source>,
-    target: Box<dyn Source>,
+    targets: Vec<Target>,
+    options: SyncOptions,
 }
 
 impl SyncJob {
-    pub fn new(origin: Box<dyn Source>, target: Box<dyn Source>) -> Self {
-        Self { origin, target }
+    pub fn new(origin: Box<dyn Source>, targets: Vec<Target>, options: SyncOptions) -> Self {
+        Self {
+            origin,
+            targets,
+            options,
+        }
+    }
+
+    fn sync_to(&self, target: &Result<Box<dyn Source>>, secrets: &Secrets) -> Result<()> {
+        // The job only borrows the error, so its message is passed on
+        let target = target.as_deref().map_err(|err| anyhow!("{err:#}"))?;
+
+        if self.options.reverse_transforms {
+            self.check_round_trip(target)?;
+        }
+
+        let with_kept = self.keep_filtered_out(target, secrets)?;
+        let secrets = with_kept.as_ref().unwrap_or(secrets);
+
+        if self.options.dry_run {
+            return self.dry_run(target, secrets);
+        }
+
+        if self.options.merge {
+            target
+                .merge_secrets(secrets)
+                .context("unable to merge secrets into target")?;
+        } else {
+            target
+                .write_secrets(secrets)
+                .context("unable to write secrets to target")?;
+        }
+
+        Ok(())
+    }
+
+    /// Add the target keys that the filter leaves out to `secrets`, so that
+    /// a sync only replaces the keys a diff would compare. Returns None when
+    /// nothing needs to be kept.
+    fn keep_filtered_out(&self, target: &dyn Source, secrets: &Secrets) -> Result<Option<Secrets>> {
+        if self.options.merge || self.options.filter.is_empty() {
+            return Ok(None);
+        }
+
+        let Some(mut kept) = existing_secrets(target)? else {
+            return Ok(None);
+        };
+
+        // Target keys are already transformed, so filter them by the name
+        // they would have in the origin
+        let inverse = self.options.transforms.inverse();
+        kept.content
+            .retain(|key, _| !self.options.filter.matches(&inverse.key(key)));
+        kept.merge(secrets);
+
+        Ok(Some(kept))
+    }
+
+    /// Reversed transforms can only restore the target keys that the
+    /// original transforms would map back to themselves, e.g. not a key
+    /// without the prefix that `strip_prefix` removes. Any other key would be
+    /// renamed by the sync, so fail before writing.
+    fn check_round_trip(&self, target: &dyn Source) -> Result<()> {
+        let Some(existing) = existing_secrets(target)? else {
+            return Ok(());
+        };
+
+        let inverse = self.options.transforms.inverse();
+        let synced = existing
+            .content
+            .keys()
+            .filter(|key| self.options.filter.matches(&inverse.key(key)));
+
+        self.options
+            .transforms
+            .check_round_trip(synced)
+            .context("reverse_transforms cannot restore the target's keys")
+    }
+
+    /// Read the target and print the changes and backend operations that a
+    /// sync would perform, without writing anything.
+    fn dry_run(&self, target: &dyn Source, secrets: &Secrets) -> Result<()> {
+        let plan = target
+            .plan_write(secrets, self.options.merge)
+            .context("unable to plan write to target")?;
+
+        let existing = if plan.exists {
+            target
+                .read_secrets()
+                .context("unable to read secrets from target")?
+        } else {
+            Secrets::new()
+        };
+
+        let (diff_lines, _, _, _) = build_diff_lines(&secrets.content, &existing.content);
+        let changes = collect_changes(&diff_lines);
+
+        let (mut added, mut changed, mut removed, mut kept) = (0, 0, 0, 0);
+
+        println!("Changes:");
+        for change in &changes {
+            let marker = match change.kind {
+                ChangeKind::Added => {
+                    added += 1;
+                    "+"
+                }
+                ChangeKind::Changed => {
+                    changed += 1;
+                    "~"
+                }
+                // Merging never deletes keys from the target
+                ChangeKind::Removed if self.options.merge => {
+                    kept += 1;
+                    continue;
+                }
+                ChangeKind::Removed => {
+                    removed += 1;
+                    "-"
+                }
+            };
+            println!("  {marker} {}", change.key);
+        }
+
+        if added + changed + removed == 0 {
+            println!("  (none)");
+        }
+
+        println!("\nPlanned operations:");
+        for operation in &plan.operations {
+            println!("  {operation}");
+        }
+
+        let mut summary = format!("\nDry run: {added} added, {changed} changed, {removed} removed");
+        if kept > 0 {
+            summary.push_str(&format!(", {kept} kept"));
+        }
+        eprintln!("{summary}. Nothing was written.");
+
+        Ok(())
     }
 }
 
+/// The secrets already in the target, or None if it doesn't exist yet.
+fn existing_secrets(target: &dyn Source) -> Result<Option<Secrets>> {
+    let plan = target
+        .plan_write(&Secrets::new(), false)
+        .context("unable to plan write to target")?;
+    if !plan.exists {
+        return Ok(None);
+    }
+
+    let existing = target
+        .read_secrets()
+        .context("unable to read secrets from target")?;
+
+    Ok(Some(existing))
+}
+
 impl super::Job for SyncJob {
-    /// Synchronize the secrets from an origin to a target
+    /// Synchronize the secrets from an origin to every target. The origin is
+    /// read once, and with several targets a failure on one of them does not
+    /// stop the others.
     fn run(&self) -> Result<()> {
-        let secrets = self
+        let mut secrets = self
             .origin
             .read_secrets()
             .context("unable to read secrets from source")?;
+        self.options.filter.apply(&mut secrets);
+        let secrets = self
+            .options
+            .transforms
+            .apply(secrets)
+            .context("unable to transform secret keys")?;
+
+        if let [(_, target)] = self.targets.as_slice() {
+            return self.sync_to(target, &secrets);
+        }
+
+        let mut failed = 0usize;
+
+        for (uri, target) in &self.targets {
+            if self.options.dry_run {
+                println!("\n==> {uri}");
+            }
 
-        self.target
-            .write_secrets(&secrets)
-            .context("unable to write secrets to target")?;
+            match self.sync_to(target, &secrets) {
+                Ok(()) if self.options.dry_run => eprintln!("Planned target '{uri}'"),
+                Ok(()) => eprintln!("Synced target '{uri}'"),
+                Err(err) => {
+                    eprintln!("Failed on target '{uri}': {err:#}");
+                    failed += 1;
+                }
+            }
+        }
+
+        let total = self.targets.len();
+        eprintln!("\n{} of {total} targets succeeded", total - failed);
+
+        if failed > 0 {
+            bail!("{failed} of {total} targets failed");
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::diff::{DiffJob, DiffOptions};
+    use crate::job::Job;
+    use crate::sources::test_source::MemorySource;
+
+    #[test]
+    fn filtered_sync_leaves_what_a_filtered_diff_ignores() {
+        let origin = MemorySource::new(&[("APP_KEY", "new")]);
+        let target = MemorySource::new(&[("APP_KEY", "old"), ("OTHER", "kept")]);
+
+        let filter = KeyFilter {
+            include: vec!["APP_*".to_string()],
+            exclude: Vec::new(),
+        };
+        let diff = |origin: &MemorySource, target: &MemorySource| {
+            DiffJob::new(
+                Box::new(origin.clone()),
+                Box::new(target.clone()),
+                "memory://".to_string(),
+                DiffOptions {
+                    exit_code: true,
+                    filter: filter.clone(),
+                    ..Default::default()
+                },
+            )
+            .run()
+        };
+
+        assert!(diff(&origin, &target).is_err());
+
+        SyncJob::new(
+            Box::new(origin.clone()),
+            vec![("memory://".to_string(), Ok(Box::new(target.clone())))],
+            SyncOptions {
+                filter: filter.clone(),
+                ..Default::default()
+            },
+        )
+        .run()
+        .unwrap();
+
+        assert!(diff(&origin, &target).is_ok());
+        assert_eq!(target.secrets()["APP_KEY"], "new");
+        assert_eq!(target.secrets()["OTHER"], "kept");
+    }
+
+    #[test]
+    fn a_target_that_cannot_be_built_does_not_stop_the_others() {
+        let origin = MemorySource::new(&[("API_KEY", "abc")]);
+        let target = MemorySource::default();
+
+        let job = SyncJob::new(
+            Box::new(origin),
+            vec![
+                (
+                    "k8s://missing/app".to_string(),
+                    Err(anyhow!("no such context")),
+                ),
+                ("memory://".to_string(), Ok(Box::new(target.clone()))),
+            ],
+            SyncOptions::default(),
+        );
+
+        let err = job.run().unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 targets failed");
+        assert_eq!(target.secrets()["API_KEY"], "abc");
+    }
+}
@@ -1,21 +1,48 @@
 use crate::config::{Args, Config};
 use anyhow::{Context, Result};
 use clap::Parser;
+use std::process::ExitCode;
 
 mod config;
+mod filter;
 mod job;
 mod secrets;
 mod sources;
+mod transform;
 
-fn main() -> Result<()> {
+/// Exit status for errors when `--exit-code` reserves 1 for drift.
+const EXIT_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
     let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) if err.is::<job::DriftDetected>() => ExitCode::FAILURE,
+        Err(err) if err.is::<job::ChildExited>() => {
+            let job::ChildExited(code) = err.downcast().expect("checked above");
+            // Codes that don't fit in an exit status still report a failure
+            u8::try_from(code).map_or(ExitCode::FAILURE, ExitCode::from)
+        }
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+
+            if args.exit_code {
+                ExitCode::from(EXIT_ERROR)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<()> {
     let cfg = Config::from_file(&args.config)
         .with_context(|| format!("failed to load config from '{}'", args.config))?;
 
     args.validate(&cfg)?;
 
-    let job = job::new_job(&cfg, args.from, args.to, args.preset, args.diff)
-        .context("could not build job")?;
+    let job = job::new_job(&cfg, args).context("could not build job")?;
 
     job.run()?;
 
@@ -1,4 +1,6 @@
 use k8s_openapi::ByteString;
+use serde::Deserialize;
+use serde_json::Value;
 use std::collections::BTreeMap;
 use std::str;
 
@@ -19,6 +21,40 @@ pub enum SecretsError {
 
     #[error("unable to write env entry")]
     WriteEntry(#[source] std::io::Error),
+
+    #[error("key '{0}' appears more than once after flattening nested values")]
+    DuplicateKey(String),
+}
+
+/// How nested objects and arrays are turned into string values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NestedValues {
+    /// Keep the nested value under its key, encoded as JSON
+    #[default]
+    Json,
+
+    /// Give every nested scalar its own key, joined with a separator
+    Flatten,
+}
+
+/// A value that could not be represented as a string without losing
+/// information, such as its type or structure.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LossyValue {
+    pub key: String,
+    pub kind: &'static str,
+    pub conversion: &'static str,
+}
+
+impl std::fmt::Display for LossyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "key '{}' holds {}; {}",
+            self.key, self.kind, self.conversion
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -35,6 +71,12 @@ impl Secrets {
         }
     }
 
+    /// Add every secret from `other`, replacing values of keys that exist in both.
+    pub fn merge(&mut self, other: &Secrets) {
+        self.content
+            .extend(other.content.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
     /// Read a buffer of dotenv-style `KEY="VALUE"` lines into a Secrets struct.
     pub fn from_reader<T: std::io::Read>(reader: &mut T) -> Result<Self, SecretsError> {
         let mut secrets = Self::new();
@@ -52,14 +94,7 @@ impl Secrets {
     /// Dollar signs are escaped as `\$` to prevent dotenvy variable substitution.
     pub fn to_writer<T: std::io::Write>(&self, buf: &mut T) -> Result<(), SecretsError> {
         for (key, value) in &self.content {
-            let encoded = serde_json::to_string(value)
-                .map_err(SecretsError::EncodeValue)?
-                // Escape `$` so that dotenvy does not perform variable substitution
-                // when reading the value back. dotenvy recognises `\$` inside
-                // double-quoted strings as a literal dollar sign.
-                .replace('$', "\\$");
-
-            let line = format!("{}={}\n", key, encoded);
+            let line = format!("{}={}\n", key, encode_value(value)?);
 
             buf.write_all(line.as_bytes())
                 .map_err(SecretsError::WriteEntry)?;
@@ -67,6 +102,95 @@ impl Secrets {
 
         Ok(())
     }
+
+    /// Convert arbitrary JSON values into secrets. Scalars become strings and
+    /// nested values are handled according to `nested`. Every value that was
+    /// not already a string is reported back.
+    pub fn from_json_map(
+        map: BTreeMap<String, Value>,
+        nested: NestedValues,
+        separator: &str,
+    ) -> Result<(Self, Vec<LossyValue>), SecretsError> {
+        let mut secrets = Self::new();
+        let mut lossy = Vec::new();
+
+        for (key, value) in map {
+            insert_json_value(&mut secrets, &mut lossy, key, value, nested, separator)?;
+        }
+
+        Ok((secrets, lossy))
+    }
+}
+
+/// Quote a value for a dotenv file, as written by `Secrets::to_writer`.
+pub fn encode_value(value: &str) -> Result<String, SecretsError> {
+    let encoded = serde_json::to_string(value)
+        .map_err(SecretsError::EncodeValue)?
+        // Escape `$` so that dotenvy does not perform variable substitution
+        // when reading the value back. dotenvy recognises `\$` inside
+        // double-quoted strings as a literal dollar sign.
+        .replace('$', "\\$");
+
+    Ok(encoded)
+}
+
+fn insert_json_value(
+    secrets: &mut Secrets,
+    lossy: &mut Vec<LossyValue>,
+    key: String,
+    value: Value,
+    nested: NestedValues,
+    separator: &str,
+) -> Result<(), SecretsError> {
+    let flatten = nested == NestedValues::Flatten;
+
+    let children: Vec<(String, Value)> = match value {
+        Value::Object(map) if flatten => map.into_iter().collect(),
+        Value::Array(items) if flatten => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| (i.to_string(), item))
+            .collect(),
+        value => {
+            let (string_value, kind, conversion) = match value {
+                Value::String(value) => (value, None, ""),
+                Value::Number(n) => (n.to_string(), Some("a number"), "converted to a string"),
+                Value::Bool(b) => (b.to_string(), Some("a boolean"), "converted to a string"),
+                Value::Null => (String::new(), Some("null"), "converted to an empty string"),
+                Value::Object(_) => (value.to_string(), Some("an object"), "encoded as JSON"),
+                Value::Array(_) => (value.to_string(), Some("an array"), "encoded as JSON"),
+            };
+
+            if let Some(kind) = kind {
+                lossy.push(LossyValue {
+                    key: key.clone(),
+                    kind,
+                    conversion,
+                });
+            }
+
+            if secrets.content.contains_key(&key) {
+                return Err(SecretsError::DuplicateKey(key));
+            }
+
+            secrets.content.insert(key, string_value);
+
+            return Ok(());
+        }
+    };
+
+    lossy.push(LossyValue {
+        key: key.clone(),
+        kind: "a nested value",
+        conversion: "flattened into separate keys",
+    });
+
+    for (child_key, child) in children {
+        let child_key = format!("{key}{separator}{child_key}");
+        insert_json_value(secrets, lossy, child_key, child, nested, separator)?;
+    }
+
+    Ok(())
 }
 
 impl From<BTreeMap<String, String>> for Secrets {
@@ -146,6 +270,26 @@ mod tests {
         assert_eq!(expected, result.content);
     }
 
+    #[test]
+    fn merge_keeps_existing_keys() {
+        let mut existing = BTreeMap::new();
+        existing.insert("KEEP".to_string(), "target".to_string());
+        existing.insert("SHARED".to_string(), "old".to_string());
+        let mut secrets = Secrets::from(existing);
+
+        let mut incoming = BTreeMap::new();
+        incoming.insert("SHARED".to_string(), "new".to_string());
+        incoming.insert("ADDED".to_string(), "origin".to_string());
+        secrets.merge(&Secrets::from(incoming));
+
+        let mut expected = BTreeMap::new();
+        expected.insert("ADDED".to_string(), "origin".to_string());
+        expected.insert("KEEP".to_string(), "target".to_string());
+        expected.insert("SHARED".to_string(), "new".to_string());
+
+        assert_eq!(secrets.content, expected);
+    }
+
     #[test]
     fn to_writer() {
         let mut map = BTreeMap::new();
@@ -248,6 +392,63 @@ mod tests {
         assert_eq!(result.content, map);
     }
 
+    #[test]
+    fn from_json_map_converts_scalars() {
+        let map: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(r#"{"name": "app", "port": 5432, "debug": true, "unset": null}"#)
+                .unwrap();
+
+        let (secrets, lossy) = Secrets::from_json_map(map, super::NestedValues::Json, "_").unwrap();
+
+        assert_eq!(secrets.content["name"], "app");
+        assert_eq!(secrets.content["port"], "5432");
+        assert_eq!(secrets.content["debug"], "true");
+        assert_eq!(secrets.content["unset"], "");
+
+        let lossy_keys: Vec<&str> = lossy.iter().map(|l| l.key.as_str()).collect();
+        assert_eq!(lossy_keys, vec!["debug", "port", "unset"]);
+    }
+
+    #[test]
+    fn from_json_map_encodes_nested_as_json() {
+        let map: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(r#"{"db": {"user": "admin"}}"#).unwrap();
+
+        let (secrets, lossy) = Secrets::from_json_map(map, super::NestedValues::Json, "_").unwrap();
+
+        assert_eq!(secrets.content["db"], r#"{"user":"admin"}"#);
+        assert_eq!(lossy.len(), 1);
+        assert_eq!(
+            lossy[0].to_string(),
+            "key 'db' holds an object; encoded as JSON"
+        );
+    }
+
+    #[test]
+    fn from_json_map_flattens_nested() {
+        let map: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(r#"{"db": {"user": "admin", "hosts": ["a", "b"]}}"#).unwrap();
+
+        let (secrets, _) = Secrets::from_json_map(map, super::NestedValues::Flatten, "__").unwrap();
+
+        let mut expected = BTreeMap::new();
+        expected.insert("db__user".to_string(), "admin".to_string());
+        expected.insert("db__hosts__0".to_string(), "a".to_string());
+        expected.insert("db__hosts__1".to_string(), "b".to_string());
+
+        assert_eq!(secrets.content, expected);
+    }
+
+    #[test]
+    fn from_json_map_rejects_flattened_collisions() {
+        let map: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(r#"{"db": {"user": "a"}, "db_user": "b"}"#).unwrap();
+
+        let result = Secrets::from_json_map(map, super::NestedValues::Flatten, "_");
+
+        assert!(matches!(result, Err(super::SecretsError::DuplicateKey(_))));
+    }
+
     #[test]
     fn existing_escapes_still_round_trip_with_dollar() {
         // Combining $ with other characters that require escaping
@@ -0,0 +1,129 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Permissions of a file written by [`write`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Keep the permissions of an existing file, and make a new one readable
+    /// only by the current user
+    KeepExisting,
+
+    /// Readable only by the current user, even if the file was not before
+    Private,
+}
+
+/// Replace the file at `path` without ever leaving it partially written: the
+/// content goes to a temporary file next to it, which is then renamed over
+/// it. A symbolic link is followed, so the file it points to is replaced
+/// rather than the link.
+///
+/// Temporary files start with `..`, which `dir://` sources skip.
+pub fn write(path: &Path, content: &[u8], mode: Mode) -> io::Result<()> {
+    let path = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(err) => return Err(err),
+    };
+    let permissions = match mode {
+        Mode::KeepExisting => fs::metadata(&path).ok().map(|meta| meta.permissions()),
+        Mode::Private => None,
+    };
+
+    let temp = temp_path(&path);
+    let result = write_temp(&temp, content, permissions).and_then(|()| fs::rename(&temp, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result
+}
+
+/// A name next to `path` that no other write uses, even from another run of
+/// scrtsync writing the same file at the same time.
+fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!("..{name}.{}.{n}.tmp", std::process::id()))
+}
+
+fn write_temp(temp: &Path, content: &[u8], permissions: Option<fs::Permissions>) -> io::Result<()> {
+    // Never open an existing file, which could be a link planted at the
+    // temporary name
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(temp)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::test_dir::TestDir;
+
+    #[cfg(unix)]
+    fn mode(path: &Path) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn new_files_are_private_and_existing_modes_are_kept() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TestDir::new("atomic-modes");
+
+        write(&dir.join("new"), b"a", Mode::KeepExisting).unwrap();
+        assert_eq!(mode(&dir.join("new")), 0o600);
+
+        fs::write(dir.join("shared"), "old").unwrap();
+        fs::set_permissions(dir.join("shared"), fs::Permissions::from_mode(0o640)).unwrap();
+        write(&dir.join("shared"), b"new", Mode::KeepExisting).unwrap();
+        assert_eq!(mode(&dir.join("shared")), 0o640);
+        assert_eq!(fs::read_to_string(dir.join("shared")).unwrap(), "new");
+
+        fs::set_permissions(dir.join("new"), fs::Permissions::from_mode(0o644)).unwrap();
+        write(&dir.join("new"), b"b", Mode::Private).unwrap();
+        assert_eq!(mode(&dir.join("new")), 0o600);
+
+        let names: Vec<_> = fs::read_dir(&*dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2, "temporary files are left behind: {names:?}");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlinks_are_followed() {
+        let dir = TestDir::new("atomic-symlink");
+        fs::write(dir.join("real.env"), "old").unwrap();
+        std::os::unix::fs::symlink("real.env", dir.join(".env")).unwrap();
+
+        write(&dir.join(".env"), b"new", Mode::KeepExisting).unwrap();
+
+        assert!(fs::symlink_metadata(dir.join(".env"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(dir.join("real.env")).unwrap(), "new");
+    }
+
+    #[test]
+    fn temporary_names_are_unique() {
+        let path = Path::new("/tmp/.env");
+        assert_ne!(temp_path(path), temp_path(path));
+    }
+}
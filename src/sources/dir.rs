@@ -0,0 +1,386 @@
+use super::{atomic, path};
+use crate::secrets::Secrets;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Entries starting with this are not keys: they are the `..data` link and
+/// timestamped directories of mounted Kubernetes volumes, the temporary files
+/// of atomic writes, or the manifest.
+const HIDDEN_PREFIX: &str = "..";
+
+/// Lists the keys whose files scrtsync wrote, one per line. Only those files
+/// are ever removed, so that syncing into a directory can't delete files
+/// that were already there.
+const MANIFEST: &str = "..scrtsync";
+
+/// A directory holding one file per key, named after the key and holding its
+/// value. This is how Kubernetes mounts secret volumes, and how Docker Swarm
+/// secrets and systemd credentials are laid out.
+pub struct DirSource {
+    path: PathBuf,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DirSourceError {
+    #[error("unable to parse directory path from URL")]
+    InvalidPath(#[from] path::PathError),
+
+    #[error("unable to read directory")]
+    ReadDir(#[source] io::Error),
+
+    #[error("unable to read the file for key '{key}'")]
+    ReadFile {
+        key: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("the file for key '{0}' is not valid UTF-8")]
+    InvalidUtf8(String),
+
+    #[error("key '{0}' cannot be used as a file name")]
+    InvalidKey(String),
+
+    #[error("unable to create directory")]
+    CreateDir(#[source] io::Error),
+
+    #[error("unable to write the file for key '{key}'")]
+    WriteFile {
+        key: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("unable to remove the file for key '{key}'")]
+    RemoveFile {
+        key: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("unable to update the list of files written by scrtsync")]
+    Manifest(#[source] io::Error),
+}
+
+impl DirSource {
+    /// Build a source from the text after `dir://`.
+    pub fn new(location: &str) -> Result<Self, DirSourceError> {
+        let (path, _query) = path::split_location(location);
+
+        Ok(DirSource {
+            path: path::resolve(path)?,
+        })
+    }
+
+    /// The files in the directory by key. Symbolic links are followed, and
+    /// subdirectories are skipped.
+    fn files(&self) -> Result<BTreeMap<String, PathBuf>, DirSourceError> {
+        let mut files = BTreeMap::new();
+
+        for entry in fs::read_dir(&self.path).map_err(DirSourceError::ReadDir)? {
+            let entry = entry.map_err(DirSourceError::ReadDir)?;
+
+            let Ok(key) = entry.file_name().into_string() else {
+                eprintln!(
+                    "Warning: skipping {}, its name is not valid UTF-8",
+                    entry.path().display()
+                );
+                continue;
+            };
+
+            let is_file = fs::metadata(entry.path()).is_ok_and(|meta| meta.is_file());
+            if key.starts_with(HIDDEN_PREFIX) || !is_file {
+                continue;
+            }
+
+            files.insert(key, entry.path());
+        }
+
+        Ok(files)
+    }
+
+    /// The keys listed in the manifest, or none if there isn't one yet.
+    fn managed_keys(&self) -> Result<BTreeSet<String>, DirSourceError> {
+        match fs::read_to_string(self.path.join(MANIFEST)) {
+            Ok(text) => Ok(text
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+            Err(err) => Err(DirSourceError::Manifest(err)),
+        }
+    }
+
+    fn write_manifest(&self, keys: &BTreeSet<String>) -> Result<(), DirSourceError> {
+        let text: String = keys.iter().map(|key| format!("{key}\n")).collect();
+        atomic::write(
+            &self.path.join(MANIFEST),
+            text.as_bytes(),
+            atomic::Mode::Private,
+        )
+        .map_err(DirSourceError::Manifest)
+    }
+}
+
+impl super::Source for DirSource {
+    fn read_secrets(&self) -> Result<Secrets, super::SourceSecretsError> {
+        eprintln!("Reading secrets from directory at {}", self.path.display());
+        let mut secrets = Secrets::new();
+
+        for (key, path) in self.files()? {
+            let content = fs::read(&path).map_err(|source| DirSourceError::ReadFile {
+                key: key.clone(),
+                source,
+            })?;
+            let value =
+                String::from_utf8(content).map_err(|_| DirSourceError::InvalidUtf8(key.clone()))?;
+
+            secrets.content.insert(key, value);
+        }
+
+        Ok(secrets)
+    }
+
+    fn write_secrets(&self, secrets: &Secrets) -> Result<(), super::SourceSecretsError> {
+        eprintln!("Writing secrets to directory at {}", self.path.display());
+
+        // Check every key up front so that a bad one doesn't leave the
+        // directory half written
+        if let Some(key) = secrets.content.keys().find(|key| !is_valid_key(key)) {
+            return Err(DirSourceError::InvalidKey(key.clone()).into());
+        }
+
+        create_dir(&self.path).map_err(DirSourceError::CreateDir)?;
+        let existing = self.files()?;
+        let previous = self.managed_keys()?;
+        let mut managed = previous.clone();
+
+        for (key, value) in &secrets.content {
+            // A file others can read is rewritten even if it holds the value
+            let unchanged = existing.get(key).is_some_and(|path| {
+                fs::read(path).is_ok_and(|content| content == value.as_bytes()) && is_private(path)
+            });
+
+            if !unchanged {
+                // A key whose file is a link, as in a mounted volume, replaces
+                // the file the link points to
+                let path = self.path.join(key);
+                atomic::write(&path, value.as_bytes(), atomic::Mode::Private).map_err(
+                    |source| DirSourceError::WriteFile {
+                        key: key.clone(),
+                        source,
+                    },
+                )?;
+                managed.insert(key.clone());
+            }
+        }
+
+        for (key, path) in existing {
+            if !secrets.content.contains_key(&key) && managed.contains(&key) {
+                fs::remove_file(path)
+                    .map_err(|source| DirSourceError::RemoveFile { key, source })?;
+            }
+        }
+
+        managed.retain(|key| secrets.content.contains_key(key));
+        if managed != previous {
+            self.write_manifest(&managed)?;
+        }
+
+        Ok(())
+    }
+
+    fn plan_write(
+        &self,
+        secrets: &Secrets,
+        merge: bool,
+    ) -> Result<super::WritePlan, super::SourceSecretsError> {
+        let exists = self.path.is_dir();
+        let path = self.path.display();
+
+        if !exists {
+            return Ok(super::WritePlan {
+                exists,
+                operations: vec![format!("create directory {path} with one file per key")],
+            });
+        }
+
+        let mut operations = vec![format!("write one file per key in {path}")];
+
+        if !merge {
+            let managed = self.managed_keys()?;
+            let (removed, kept): (Vec<_>, Vec<_>) = self
+                .files()?
+                .into_keys()
+                .filter(|key| !secrets.content.contains_key(key))
+                .partition(|key| managed.contains(key));
+
+            if !removed.is_empty() {
+                operations.push(format!(
+                    "remove files that scrtsync wrote for keys that are no longer synced: {}",
+                    removed.join(", ")
+                ));
+            }
+            if !kept.is_empty() {
+                operations.push(format!(
+                    "keep files that scrtsync didn't write: {}",
+                    kept.join(", ")
+                ));
+            }
+        }
+
+        Ok(super::WritePlan { exists, operations })
+    }
+
+    fn merge_secrets(&self, secrets: &Secrets) -> Result<(), super::SourceSecretsError> {
+        if !self.path.is_dir() {
+            return self.write_secrets(secrets);
+        }
+
+        let mut merged = self.read_secrets()?;
+        merged.merge(secrets);
+        self.write_secrets(&merged)
+    }
+}
+
+/// Keys become file names, so they can't leave the directory or be mistaken
+/// for the entries that are skipped when reading.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key != "."
+        && !key.starts_with(HIDDEN_PREFIX)
+        && !key.contains(['/', '\\', '\0'])
+}
+
+#[cfg(unix)]
+fn is_private(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o077 == 0)
+}
+
+#[cfg(not(unix))]
+fn is_private(_path: &Path) -> bool {
+    true
+}
+
+fn create_dir(path: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    builder.create(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::test_dir::TestDir;
+    use crate::sources::Source;
+
+    fn source(dir: &Path) -> DirSource {
+        DirSource {
+            path: dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn mounted_volume_layout_is_read() {
+        let dir = TestDir::new("mounted");
+        fs::create_dir_all(dir.join("..2024_01_01").join("nested")).unwrap();
+        fs::write(dir.join("..2024_01_01").join("password"), "hunter2").unwrap();
+        fs::write(dir.join("username"), "admin\n").unwrap();
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("..2024_01_01", dir.join("..data")).unwrap();
+            std::os::unix::fs::symlink("..data/password", dir.join("password")).unwrap();
+        }
+
+        let secrets = source(&dir).read_secrets().unwrap();
+        assert_eq!(secrets.content["username"], "admin\n");
+        #[cfg(unix)]
+        assert_eq!(secrets.content["password"], "hunter2");
+        assert!(!secrets.content.keys().any(|key| key.starts_with("..")));
+    }
+
+    #[test]
+    fn write_replaces_files_and_removes_deleted_keys() {
+        let dir = TestDir::new("write");
+        let mut secrets = Secrets::new();
+        secrets
+            .content
+            .insert("OLD".to_string(), "gone".to_string());
+        source(&dir).write_secrets(&secrets).unwrap();
+
+        // Written by someone else, so never removed
+        fs::write(dir.join("NOTES"), "kept").unwrap();
+
+        let mut secrets = Secrets::new();
+        secrets
+            .content
+            .insert("API_KEY".to_string(), "abc".to_string());
+        source(&dir).write_secrets(&secrets).unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&*dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["..scrtsync", "API_KEY", "NOTES"]);
+        assert_eq!(fs::read_to_string(dir.join(MANIFEST)).unwrap(), "API_KEY\n");
+        assert_eq!(fs::read_to_string(dir.join("API_KEY")).unwrap(), "abc");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.join("API_KEY"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            fs::set_permissions(dir.join("API_KEY"), fs::Permissions::from_mode(0o644)).unwrap();
+            source(&dir).write_secrets(&secrets).unwrap();
+            let mode = fs::metadata(dir.join("API_KEY"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn plan_only_lists_files_that_would_be_removed() {
+        let dir = TestDir::new("plan");
+        let mut secrets = Secrets::new();
+        for key in ["KEPT", "GONE"] {
+            secrets.content.insert(key.to_string(), "x".to_string());
+        }
+        source(&dir).write_secrets(&secrets).unwrap();
+        fs::write(dir.join("NOTES"), "kept").unwrap();
+
+        secrets.content.remove("GONE");
+        let plan = source(&dir).plan_write(&secrets, false).unwrap();
+
+        assert_eq!(
+            plan.operations[1..],
+            [
+                "remove files that scrtsync wrote for keys that are no longer synced: GONE",
+                "keep files that scrtsync didn't write: NOTES",
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_that_escape_the_directory_are_rejected() {
+        for key in ["", ".", "..data", "../outside", "a/b"] {
+            assert!(!is_valid_key(key), "{key:?} should be rejected");
+        }
+        assert!(is_valid_key(".hidden"));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::secrets::Secrets;
+
+/// Reads secrets from the environment of the current process, optionally
+/// only the variables starting with a prefix.
+pub struct EnvSource {
+    prefix: String,
+    strip_prefix: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvSourceError {
+    #[error("invalid strip_prefix value '{0}', expected true or false")]
+    InvalidStripPrefix(String),
+
+    #[error("the process environment is read-only and cannot be a target")]
+    ReadOnly,
+}
+
+impl EnvSource {
+    pub fn new(url: &url::Url) -> Result<Self, EnvSourceError> {
+        let mut prefix = String::new();
+        let mut strip_prefix = false;
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "prefix" => prefix = value.into_owned(),
+                "strip_prefix" => {
+                    strip_prefix = value
+                        .parse()
+                        .map_err(|_| EnvSourceError::InvalidStripPrefix(value.into_owned()))?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            prefix,
+            strip_prefix,
+        })
+    }
+
+    /// Keep the variables that match the prefix. Variables that would have
+    /// an empty name after stripping the prefix are skipped.
+    fn select(&self, vars: impl Iterator<Item = (String, String)>) -> Secrets {
+        let mut secrets = Secrets::new();
+
+        for (key, value) in vars {
+            let Some(rest) = key.strip_prefix(&self.prefix) else {
+                continue;
+            };
+
+            if !self.strip_prefix {
+                secrets.content.insert(key, value);
+            } else if !rest.is_empty() {
+                secrets.content.insert(rest.to_string(), value);
+            }
+        }
+
+        secrets
+    }
+}
+
+impl super::Source for EnvSource {
+    /// Read the matching variables. Variables whose name or value is not
+    /// valid Unicode are skipped.
+    fn read_secrets(&self) -> Result<Secrets, super::SourceSecretsError> {
+        let vars = std::env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)));
+
+        Ok(self.select(vars))
+    }
+
+    fn write_secrets(&self, _secrets: &Secrets) -> Result<(), super::SourceSecretsError> {
+        Err(EnvSourceError::ReadOnly.into())
+    }
+
+    fn merge_secrets(&self, _secrets: &Secrets) -> Result<(), super::SourceSecretsError> {
+        Err(EnvSourceError::ReadOnly.into())
+    }
+
+    fn plan_write(
+        &self,
+        _secrets: &Secrets,
+        _merge: bool,
+    ) -> Result<super::WritePlan, super::SourceSecretsError> {
+        Err(EnvSourceError::ReadOnly.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> impl Iterator<Item = (String, String)> {
+        [
+            ("APP_DB_URL", "postgres://"),
+            ("APP_", "empty"),
+            ("HOME", "/root"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+    }
+
+    fn source(uri: &str) -> EnvSource {
+        EnvSource::new(&url::Url::parse(uri).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn no_prefix_reads_everything() {
+        let secrets = source("env://").select(vars());
+        assert_eq!(secrets.content.len(), 3);
+    }
+
+    #[test]
+    fn prefix_filters_variables() {
+        let secrets = source("env://?prefix=APP_").select(vars());
+        let keys: Vec<_> = secrets.content.keys().collect();
+        assert_eq!(keys, ["APP_", "APP_DB_URL"]);
+    }
+
+    #[test]
+    fn strip_prefix_renames_variables() {
+        let secrets = source("env://?prefix=APP_&strip_prefix=true").select(vars());
+        let keys: Vec<_> = secrets.content.keys().collect();
+        assert_eq!(keys, ["DB_URL"]);
+    }
+
+    #[test]
+    fn invalid_strip_prefix_is_an_error() {
+        let url = url::Url::parse("env://?strip_prefix=yes").unwrap();
+        assert!(matches!(
+            EnvSource::new(&url),
+            Err(EnvSourceError::InvalidStripPrefix(_))
+        ));
+    }
+}
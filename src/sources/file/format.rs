@@ -0,0 +1,352 @@
+use super::{properties, shell, FileSourceError};
+use crate::secrets::{NestedValues, Secrets};
+use serde_json::{map::Entry, Map, Value};
+use std::path::Path;
+
+/// Separator used to flatten nested keys when none is given.
+pub const DEFAULT_SEPARATOR: &str = ".";
+
+/// How secrets are laid out in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// `KEY="value"` lines
+    Env,
+
+    Json,
+    Yaml,
+    Toml,
+
+    /// Java `.properties`
+    Properties,
+
+    /// POSIX shell `export KEY='value'` lines, for `eval`
+    Sh,
+
+    /// fish `set -gx KEY 'value'` lines
+    Fish,
+
+    /// PowerShell `$env:KEY = 'value'` lines
+    PowerShell,
+
+    /// Unquoted `KEY=value` lines, for `docker run --env-file`
+    Docker,
+}
+
+impl std::fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FileFormat::Env => "env",
+            FileFormat::Json => "json",
+            FileFormat::Yaml => "yaml",
+            FileFormat::Toml => "toml",
+            FileFormat::Properties => "properties",
+            FileFormat::Sh => "sh",
+            FileFormat::Fish => "fish",
+            FileFormat::PowerShell => "powershell",
+            FileFormat::Docker => "docker",
+        })
+    }
+}
+
+impl FileFormat {
+    /// Look up a format by the name given in `?format=` or a file extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "env" | "dotenv" => Some(FileFormat::Env),
+            "json" => Some(FileFormat::Json),
+            "yaml" | "yml" => Some(FileFormat::Yaml),
+            "toml" => Some(FileFormat::Toml),
+            "properties" => Some(FileFormat::Properties),
+            "sh" => Some(FileFormat::Sh),
+            "fish" => Some(FileFormat::Fish),
+            "powershell" | "ps1" => Some(FileFormat::PowerShell),
+            "docker" => Some(FileFormat::Docker),
+            _ => None,
+        }
+    }
+
+    /// Pick the format from the file extension. Files such as `.env`,
+    /// `app.env`, `.env.local` or `.env.docker` are dotenv files: Docker env
+    /// files are only written when asked for by name.
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match ext.as_str() {
+            "json" => FileFormat::Json,
+            "yaml" | "yml" => FileFormat::Yaml,
+            "toml" => FileFormat::Toml,
+            "properties" => FileFormat::Properties,
+            "sh" => FileFormat::Sh,
+            "fish" => FileFormat::Fish,
+            "ps1" => FileFormat::PowerShell,
+            _ => FileFormat::Env,
+        }
+    }
+
+    /// Shell formats can only be written: evaluating them is the only way to
+    /// read them back.
+    pub fn is_write_only(self) -> bool {
+        matches!(
+            self,
+            FileFormat::Sh | FileFormat::Fish | FileFormat::PowerShell
+        )
+    }
+
+    /// Read the contents of a file. Nested values are flattened into
+    /// separate keys, joined with `separator`.
+    pub fn read(self, text: &str, separator: &str) -> Result<Secrets, FileSourceError> {
+        let value = match self {
+            FileFormat::Env => {
+                return Secrets::from_reader(&mut text.as_bytes()).map_err(FileSourceError::Parse)
+            }
+            FileFormat::Properties => return Ok(properties::parse(text)),
+            FileFormat::Docker => return Ok(shell::parse_docker(text)),
+            FileFormat::Sh | FileFormat::Fish | FileFormat::PowerShell => {
+                return Err(FileSourceError::WriteOnly(self))
+            }
+            // An empty file holds no secrets, even if the format would
+            // otherwise reject it
+            _ if text.trim().is_empty() => return Ok(Secrets::new()),
+            FileFormat::Json => serde_json::from_str(text).map_err(|err| self.decode_error(err))?,
+            FileFormat::Yaml => serde_yaml::from_str(text).map_err(|err| self.decode_error(err))?,
+            FileFormat::Toml => toml_to_json(toml::Value::Table(
+                text.parse().map_err(|err| self.decode_error(err))?,
+            )),
+        };
+
+        let map = match value {
+            Value::Object(map) => map.into_iter().collect(),
+            Value::Null => return Ok(Secrets::new()),
+            _ => return Err(FileSourceError::NotAnObject(self)),
+        };
+
+        let (secrets, lossy) = Secrets::from_json_map(map, NestedValues::Flatten, separator)
+            .map_err(FileSourceError::Parse)?;
+
+        // Flattening is undone on write, so only converted scalars are worth
+        // a warning
+        for value in lossy.iter().filter(|value| value.kind != "a nested value") {
+            eprintln!("Warning: {value}");
+        }
+
+        Ok(secrets)
+    }
+
+    /// Render secrets as the contents of a file. Keys containing `separator`
+    /// become nested values again.
+    pub fn write(self, secrets: &Secrets, separator: &str) -> Result<String, FileSourceError> {
+        match self {
+            FileFormat::Env => {
+                let mut buf = Vec::new();
+                secrets
+                    .to_writer(&mut buf)
+                    .map_err(FileSourceError::Write)?;
+                Ok(String::from_utf8(buf).expect("secrets are valid UTF-8"))
+            }
+            FileFormat::Properties => Ok(properties::to_string(secrets)),
+            FileFormat::Sh | FileFormat::Fish | FileFormat::PowerShell | FileFormat::Docker => {
+                shell::to_string(secrets, self)
+            }
+            FileFormat::Json => {
+                let value = nest(secrets, separator)?;
+                let text =
+                    serde_json::to_string_pretty(&value).map_err(|err| self.encode_error(err))?;
+                Ok(text + "\n")
+            }
+            FileFormat::Yaml => serde_yaml::to_string(&nest(secrets, separator)?)
+                .map_err(|err| self.encode_error(err)),
+            FileFormat::Toml => toml::to_string_pretty(&nest(secrets, separator)?)
+                .map_err(|err| self.encode_error(err)),
+        }
+    }
+
+    fn decode_error(self, err: impl std::error::Error + Send + Sync + 'static) -> FileSourceError {
+        FileSourceError::Decode {
+            format: self,
+            source: Box::new(err),
+        }
+    }
+
+    fn encode_error(self, err: impl std::error::Error + Send + Sync + 'static) -> FileSourceError {
+        FileSourceError::Encode {
+            format: self,
+            source: Box::new(err),
+        }
+    }
+}
+
+/// Rebuild nested objects from flattened keys. Objects whose keys are
+/// exactly `0` to `n - 1` become arrays again.
+fn nest(secrets: &Secrets, separator: &str) -> Result<Value, FileSourceError> {
+    let mut root = Map::new();
+
+    for (key, value) in &secrets.content {
+        let path: Vec<&str> = if separator.is_empty() {
+            vec![key]
+        } else {
+            key.split(separator).collect()
+        };
+        let (last, parents) = path
+            .split_last()
+            .expect("split yields at least one segment");
+
+        let mut node = &mut root;
+        for parent in parents {
+            let child = node
+                .entry(*parent)
+                .or_insert_with(|| Value::Object(Map::new()));
+
+            node = match child {
+                Value::Object(map) => map,
+                _ => return Err(FileSourceError::KeyConflict(key.clone())),
+            };
+        }
+
+        match node.entry(*last) {
+            Entry::Vacant(entry) => {
+                entry.insert(Value::String(value.clone()));
+            }
+            Entry::Occupied(_) => return Err(FileSourceError::KeyConflict(key.clone())),
+        }
+    }
+
+    Ok(Value::Object(
+        root.into_iter()
+            .map(|(key, value)| (key, restore_arrays(value)))
+            .collect(),
+    ))
+}
+
+fn restore_arrays(value: Value) -> Value {
+    let Value::Object(map) = value else {
+        return value;
+    };
+
+    let mut map: Map<String, Value> = map
+        .into_iter()
+        .map(|(key, value)| (key, restore_arrays(value)))
+        .collect();
+
+    let is_array = !map.is_empty() && (0..map.len()).all(|i| map.contains_key(&i.to_string()));
+    if !is_array {
+        return Value::Object(map);
+    }
+
+    Value::Array(
+        (0..map.len())
+            .map(|i| map.remove(&i.to_string()).expect("checked above"))
+            .collect(),
+    )
+}
+
+/// Convert TOML into JSON, turning dates and times into strings.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => serde_json::Number::from_f64(f)
+            .map_or_else(|| Value::String(f.to_string()), Value::Number),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::file::secrets;
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(FileFormat::from_path(Path::new(".env")), FileFormat::Env);
+        assert_eq!(
+            FileFormat::from_path(Path::new(".env.local")),
+            FileFormat::Env
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("values.YML")),
+            FileFormat::Yaml
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("application.properties")),
+            FileFormat::Properties
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("env.ps1")),
+            FileFormat::PowerShell
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new(".env.docker")),
+            FileFormat::Env
+        );
+    }
+
+    #[test]
+    fn nested_values_are_flattened_on_read() {
+        let yaml = "db:\n  host: localhost\n  port: 5432\nhosts:\n  - a\n  - b\n";
+        let flat = FileFormat::Yaml.read(yaml, "__").unwrap();
+
+        assert_eq!(
+            flat.content,
+            secrets(&[
+                ("db__host", "localhost"),
+                ("db__port", "5432"),
+                ("hosts__0", "a"),
+                ("hosts__1", "b"),
+            ])
+            .content
+        );
+    }
+
+    #[test]
+    fn nested_values_are_rebuilt_on_write() {
+        let flat = secrets(&[
+            ("API_KEY", "abc"),
+            ("db.host", "localhost"),
+            ("db.hosts.0", "a"),
+            ("db.hosts.1", "b"),
+        ]);
+
+        let json = FileFormat::Json.write(&flat, ".").unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&json).unwrap(),
+            serde_json::json!({
+                "API_KEY": "abc",
+                "db": { "host": "localhost", "hosts": ["a", "b"] }
+            })
+        );
+
+        for format in [FileFormat::Json, FileFormat::Yaml, FileFormat::Toml] {
+            let text = format.write(&flat, ".").unwrap();
+            assert_eq!(format.read(&text, ".").unwrap().content, flat.content);
+        }
+    }
+
+    #[test]
+    fn toml_dates_are_strings() {
+        let secrets = FileFormat::Toml
+            .read("[cert]\nexpires = 2025-01-01T00:00:00Z\n", ".")
+            .unwrap();
+        assert_eq!(secrets.content["cert.expires"], "2025-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn value_and_parent_with_same_key_conflict() {
+        let flat = secrets(&[("db", "x"), ("db.host", "y")]);
+        assert!(matches!(
+            FileFormat::Json.write(&flat, "."),
+            Err(FileSourceError::KeyConflict(key)) if key == "db.host"
+        ));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::secrets::{encode_value, Secrets, SecretsError};
+use std::collections::BTreeSet;
+
+/// Comment above the keys that scrtsync appends to an existing file.
+const ADDED_MARKER: &str = "# Added by scrtsync";
+
+/// A piece of an existing `.env` file.
+#[derive(Debug, PartialEq, Eq)]
+enum Part<'a> {
+    /// Anything that isn't an assignment: comments, blank lines, and lines
+    /// we don't understand. Kept as is.
+    Text(&'a str),
+
+    /// `KEY=value`, split so that only the value needs to be replaced.
+    Entry {
+        key: &'a str,
+
+        /// Indentation, `export`, the key and `=`
+        lead: &'a str,
+
+        /// The value as written, including any quotes
+        value: &'a str,
+
+        /// A trailing comment and the line ending
+        tail: &'a str,
+    },
+}
+
+/// Rewrite an existing `.env` file so that it holds exactly `secrets`.
+///
+/// Changed values are replaced in place, keys that are no longer present are
+/// removed, and new keys are appended below a marker comment. Comments,
+/// blank lines, ordering and unchanged values are kept byte for byte.
+pub fn update(existing: &str, secrets: &Secrets) -> Result<String, SecretsError> {
+    let parts = parse(existing);
+
+    // Values can refer to earlier ones, so they are compared as read from
+    // the whole file
+    let current = Secrets::from_reader(&mut existing.as_bytes()).ok();
+
+    let mut seen = BTreeSet::new();
+    let mut out = String::with_capacity(existing.len());
+
+    for part in &parts {
+        match *part {
+            Part::Text(text) => out.push_str(text),
+            Part::Entry {
+                key,
+                lead,
+                value,
+                tail,
+            } => {
+                let Some(new_value) = secrets.content.get(key) else {
+                    continue;
+                };
+                seen.insert(key);
+
+                out.push_str(lead);
+                let old_value = match &current {
+                    Some(current) => current.content.get(key).cloned(),
+                    None => parse_value(key, value),
+                };
+                if old_value.as_ref() == Some(new_value) {
+                    out.push_str(value);
+                } else {
+                    out.push_str(&encode_value(new_value)?);
+                }
+                out.push_str(tail);
+            }
+        }
+    }
+
+    let mut added = String::new();
+    for (key, value) in &secrets.content {
+        if !seen.contains(key.as_str()) {
+            added.push_str(&format!("{key}={}\n", encode_value(value)?));
+        }
+    }
+
+    if added.is_empty() {
+        return Ok(out);
+    }
+
+    Ok(insert_added(out, &added))
+}
+
+/// Put new entries at the end of the marked section, starting one at the
+/// end of the file if there is none yet.
+fn insert_added(mut out: String, added: &str) -> String {
+    let lines: Vec<&str> = out.split_inclusive('\n').collect();
+
+    if let Some(marker) = lines
+        .iter()
+        .position(|line| line.trim_end() == ADDED_MARKER)
+    {
+        let section_end = lines[marker + 1..]
+            .iter()
+            .position(|line| line.trim().is_empty())
+            .map_or(lines.len(), |offset| marker + 1 + offset);
+
+        let mut before: String = lines[..section_end].concat();
+        if !before.ends_with('\n') {
+            before.push('\n');
+        }
+
+        return before + added + &lines[section_end..].concat();
+    }
+
+    if !out.is_empty() {
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        if !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+
+    out + ADDED_MARKER + "\n" + added
+}
+
+/// Read the value of a single entry, for files that can't be read as a whole.
+fn parse_value(key: &str, value: &str) -> Option<String> {
+    let line = format!("{key}={value}\n");
+    let mut secrets = Secrets::from_reader(&mut line.as_bytes()).ok()?;
+    secrets.content.remove(key)
+}
+
+fn parse(text: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+
+        match parse_entry(rest) {
+            Some((entry, len)) => {
+                parts.push(entry);
+                rest = &rest[len..];
+            }
+            None => {
+                parts.push(Part::Text(&rest[..line_end]));
+                rest = &rest[line_end..];
+            }
+        }
+    }
+
+    parts
+}
+
+/// Parse an assignment at the start of `text`, returning it and how many
+/// bytes it spans. Quoted values may continue over several lines.
+fn parse_entry(text: &str) -> Option<(Part<'_>, usize)> {
+    let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+    let mut pos = indent;
+
+    if let Some(after) = text[pos..].strip_prefix("export ") {
+        pos = text.len() - after.trim_start_matches(' ').len();
+    }
+
+    let key_len = text[pos..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')))
+        .unwrap_or(text.len() - pos);
+    if key_len == 0 {
+        return None;
+    }
+    let key = &text[pos..pos + key_len];
+    pos += key_len;
+
+    pos = text.len() - text[pos..].trim_start_matches([' ', '\t']).len();
+    pos += text[pos..].strip_prefix('=').map(|_| 1)?;
+    pos = text.len() - text[pos..].trim_start_matches([' ', '\t']).len();
+    let lead = &text[..pos];
+
+    let value_len = match text[pos..].chars().next() {
+        Some(quote @ ('"' | '\'')) => closing_quote(&text[pos + 1..], quote)? + 2,
+        _ => {
+            let line = text[pos..].lines().next().unwrap_or_default();
+            // Unquoted values end where a ` #` comment starts
+            let value = line.find(" #").map_or(line, |i| &line[..i]);
+            value.trim_end_matches([' ', '\t']).len()
+        }
+    };
+    let value = &text[pos..pos + value_len];
+    pos += value_len;
+
+    let line_end = text[pos..].find('\n').map_or(text.len(), |i| pos + i + 1);
+    let tail = &text[pos..line_end];
+
+    Some((
+        Part::Entry {
+            key,
+            lead,
+            value,
+            tail,
+        },
+        line_end,
+    ))
+}
+
+/// Find the quote that closes a value, skipping backslash escapes in
+/// double-quoted values.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::file::secrets;
+
+    const FILE: &str = "# Database\nDB_HOST=localhost\nDB_PASS='secret' # rotate me\n\n# API\nexport API_KEY=\"abc\"\n";
+
+    #[test]
+    fn unchanged_file_is_kept_byte_for_byte() {
+        let current = Secrets::from_reader(&mut FILE.as_bytes()).unwrap();
+        assert_eq!(update(FILE, &current).unwrap(), FILE);
+    }
+
+    #[test]
+    fn substituted_values_are_compared_as_read() {
+        let file = "HOST=localhost\nURL=${HOST}/x\n";
+        let current = Secrets::from_reader(&mut file.as_bytes()).unwrap();
+        assert_eq!(current.content["URL"], "localhost/x");
+        assert_eq!(update(file, &current).unwrap(), file);
+    }
+
+    #[test]
+    fn changed_values_are_replaced_in_place() {
+        let new = secrets(&[("DB_HOST", "db"), ("DB_PASS", "secret"), ("API_KEY", "xyz")]);
+        assert_eq!(
+            update(FILE, &new).unwrap(),
+            "# Database\nDB_HOST=\"db\"\nDB_PASS='secret' # rotate me\n\n# API\nexport API_KEY=\"xyz\"\n"
+        );
+    }
+
+    #[test]
+    fn removed_keys_are_dropped() {
+        let new = secrets(&[("DB_HOST", "localhost"), ("API_KEY", "abc")]);
+        assert_eq!(
+            update(FILE, &new).unwrap(),
+            "# Database\nDB_HOST=localhost\n\n# API\nexport API_KEY=\"abc\"\n"
+        );
+    }
+
+    #[test]
+    fn new_keys_are_appended_under_marker() {
+        let file = "A=1\n";
+        let once = update(file, &secrets(&[("A", "1"), ("B", "2")])).unwrap();
+        assert_eq!(once, "A=1\n\n# Added by scrtsync\nB=\"2\"\n");
+
+        let twice = update(&once, &secrets(&[("A", "1"), ("B", "2"), ("C", "3")])).unwrap();
+        assert_eq!(twice, "A=1\n\n# Added by scrtsync\nB=\"2\"\nC=\"3\"\n");
+    }
+
+    #[test]
+    fn multiline_values_are_one_entry() {
+        let file = "CERT=\"line one\nline two\"\nNEXT=1\n";
+        let current = Secrets::from_reader(&mut file.as_bytes()).unwrap();
+        assert_eq!(current.content["CERT"], "line one\nline two");
+        assert_eq!(update(file, &current).unwrap(), file);
+    }
+}
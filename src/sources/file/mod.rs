@@ -0,0 +1,323 @@
+use super::{atomic, path};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+mod format;
+mod layout;
+mod properties;
+mod shell;
+
+pub use format::{FileFormat, DEFAULT_SEPARATOR};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileSourceError {
+    #[error("unable to parse file path from URL")]
+    InvalidPath(#[from] path::PathError),
+
+    #[error("unable to open file for reading")]
+    OpenFile(#[source] std::io::Error),
+
+    #[error("unknown file format '{0}', expected env, json, yaml, toml, properties, sh, fish, powershell or docker")]
+    UnknownFormat(String),
+
+    #[error("unable to parse secrets")]
+    Parse(#[source] crate::secrets::SecretsError),
+
+    #[error("unable to write secrets")]
+    Write(#[source] crate::secrets::SecretsError),
+
+    #[error("unable to write file")]
+    WriteFile(#[source] std::io::Error),
+
+    #[error("unable to back up file")]
+    Backup(#[source] std::io::Error),
+
+    #[error("unable to parse {format} file")]
+    Decode {
+        format: FileFormat,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("unable to encode secrets as {format}")]
+    Encode {
+        format: FileFormat,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("a {0} file must hold an object at the top level")]
+    NotAnObject(FileFormat),
+
+    #[error("key '{0}' cannot be nested under a key that already holds a value")]
+    KeyConflict(String),
+
+    #[error("{0} files can only be written, not read")]
+    WriteOnly(FileFormat),
+
+    #[error("key '{key}' is not a valid variable name for {format}")]
+    InvalidName { key: String, format: FileFormat },
+
+    #[error("the value of '{key}' spans several lines, which {format} files cannot hold")]
+    MultilineValue { key: String, format: FileFormat },
+}
+
+pub struct FileSource {
+    path: PathBuf,
+    format: FileFormat,
+
+    /// Joins the keys of nested values in JSON, YAML and TOML files
+    separator: String,
+
+    /// Keep a timestamped copy of the file before changing it
+    backup: bool,
+}
+
+impl FileSource {
+    /// Build a source from the text after `file://`. Without a `?format=`
+    /// parameter, the format from `options` is used, then the file extension.
+    pub fn new(location: &str, options: &super::SourceOptions) -> Result<Self, FileSourceError> {
+        let (path, query) = path::split_location(location);
+        let path = path::resolve(path)?;
+
+        let mut format = None;
+        let mut separator = DEFAULT_SEPARATOR.to_string();
+
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match key.as_ref() {
+                "format" => {
+                    format = Some(
+                        FileFormat::from_name(&value)
+                            .ok_or_else(|| FileSourceError::UnknownFormat(value.into_owned()))?,
+                    )
+                }
+                "separator" => separator = value.into_owned(),
+                _ => {}
+            }
+        }
+
+        Ok(FileSource {
+            format: format
+                .or(options.format)
+                .unwrap_or_else(|| FileFormat::from_path(&path)),
+            path,
+            separator,
+            backup: options.backup,
+        })
+    }
+
+    /// Save the current content of the file next to it, named after the
+    /// time of the backup and readable only by the current user.
+    fn back_up(&self, content: &str, timestamp: &str) -> Result<PathBuf, FileSourceError> {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        // Don't overwrite an earlier backup from within the same second, even
+        // one made by another run at the same time
+        let mut n = 0;
+        loop {
+            let backup = match n {
+                0 => self.path.with_file_name(format!("{name}.{timestamp}.bak")),
+                n => self
+                    .path
+                    .with_file_name(format!("{name}.{timestamp}-{n}.bak")),
+            };
+
+            match options.open(&backup) {
+                Ok(mut file) => {
+                    file.write_all(content.as_bytes())
+                        .and_then(|()| file.sync_all())
+                        .map_err(FileSourceError::Backup)?;
+                    return Ok(backup);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(err) => return Err(FileSourceError::Backup(err)),
+            }
+        }
+    }
+}
+
+impl super::Source for FileSource {
+    fn read_secrets(&self) -> Result<crate::secrets::Secrets, super::SourceSecretsError> {
+        eprintln!("Reading secrets from file at {}", self.path.display());
+        let text = std::fs::read_to_string(&self.path).map_err(FileSourceError::OpenFile)?;
+        let secrets = self.format.read(&text, &self.separator)?;
+
+        Ok(secrets)
+    }
+
+    fn write_secrets(
+        &self,
+        secrets: &crate::secrets::Secrets,
+    ) -> Result<(), super::SourceSecretsError> {
+        eprintln!("Writing secrets to file at {}", self.path.display());
+
+        let existing = self
+            .path
+            .exists()
+            .then(|| std::fs::read_to_string(&self.path))
+            .transpose()
+            .map_err(FileSourceError::OpenFile)?;
+
+        let content = match (&existing, self.format) {
+            // Update existing .env files in place to keep their comments and layout
+            (Some(existing), FileFormat::Env) => {
+                layout::update(existing, secrets).map_err(FileSourceError::Write)?
+            }
+            (_, format) => format.write(secrets, &self.separator)?,
+        };
+
+        if existing.as_ref() == Some(&content) {
+            return Ok(());
+        }
+
+        if let Some(existing) = existing.as_ref().filter(|_| self.backup) {
+            let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+            let backup = self.back_up(existing, &timestamp)?;
+            eprintln!("Backed up previous secrets to {}", backup.display());
+        }
+
+        atomic::write(&self.path, content.as_bytes(), atomic::Mode::KeepExisting)
+            .map_err(FileSourceError::WriteFile)?;
+
+        Ok(())
+    }
+
+    fn plan_write(
+        &self,
+        _secrets: &crate::secrets::Secrets,
+        merge: bool,
+    ) -> Result<super::WritePlan, super::SourceSecretsError> {
+        let exists = self.path.exists();
+
+        let path = self.path.display();
+
+        let operation = match (exists, self.format, merge) {
+            (false, format, _) => format!("create {format} file {path}"),
+            (true, FileFormat::Env, false) => format!("update file {path} in place"),
+            (true, FileFormat::Env, true) => {
+                format!("update file {path} in place keeping existing keys")
+            }
+            (true, format, false) => format!("overwrite {format} file {path}"),
+            (true, format, true) => format!("overwrite {format} file {path} keeping existing keys"),
+        };
+
+        let mut operations = vec![operation];
+        if self.backup && exists {
+            operations.insert(0, format!("back up file {path} if it changes"));
+        }
+
+        // A file that can't be read back holds nothing to compare or keep
+        Ok(super::WritePlan {
+            exists: exists && !self.format.is_write_only(),
+            operations,
+        })
+    }
+
+    fn merge_secrets(
+        &self,
+        secrets: &crate::secrets::Secrets,
+    ) -> Result<(), super::SourceSecretsError> {
+        if !self.path.exists() {
+            return self.write_secrets(secrets);
+        }
+
+        if self.format.is_write_only() {
+            eprintln!(
+                "Warning: {} files can't be read, so keys only in {} are not kept",
+                self.format,
+                self.path.display()
+            );
+            return self.write_secrets(secrets);
+        }
+
+        let mut merged = self.read_secrets()?;
+        merged.merge(secrets);
+        self.write_secrets(&merged)
+    }
+}
+
+/// Secrets from key and value pairs, for the tests of each format.
+#[cfg(test)]
+fn secrets(pairs: &[(&str, &str)]) -> crate::secrets::Secrets {
+    let mut secrets = crate::secrets::Secrets::new();
+    for (key, value) in pairs {
+        secrets.content.insert(key.to_string(), value.to_string());
+    }
+    secrets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::Secrets;
+    use crate::sources::test_dir::TestDir;
+    use crate::sources::{Source, SourceOptions};
+    use std::fs;
+
+    fn backups(dir: &std::path::Path) -> Vec<PathBuf> {
+        let mut backups: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "bak"))
+            .collect();
+        backups.sort();
+        backups
+    }
+
+    fn backed_up_source(dir: &std::path::Path) -> FileSource {
+        let options = SourceOptions {
+            backup: true,
+            ..Default::default()
+        };
+        FileSource::new(&dir.join(".env").to_string_lossy(), &options).unwrap()
+    }
+
+    #[test]
+    fn changed_files_are_backed_up_once() {
+        let dir = TestDir::new("backup-changed");
+        fs::write(dir.join(".env"), "A=\"old\"\n").unwrap();
+        let source = backed_up_source(&dir);
+
+        source.write_secrets(&secrets(&[("A", "new")])).unwrap();
+        // Unchanged, so there is nothing to back up
+        source.write_secrets(&secrets(&[("A", "new")])).unwrap();
+
+        let backups = backups(&dir);
+        assert_eq!(backups.len(), 1, "{backups:?}");
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "A=\"old\"\n");
+        assert_eq!(fs::read_to_string(dir.join(".env")).unwrap(), "A=\"new\"\n");
+    }
+
+    #[test]
+    fn write_only_files_are_replaced_when_merging() {
+        let dir = TestDir::new("write-only");
+        let path = dir.join("env.sh");
+        let source = FileSource::new(&path.to_string_lossy(), &SourceOptions::default()).unwrap();
+
+        source.write_secrets(&secrets(&[("OLD", "1")])).unwrap();
+        assert!(!source.plan_write(&Secrets::new(), true).unwrap().exists);
+
+        source.merge_secrets(&secrets(&[("NEW", "2")])).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "export NEW='2'\n");
+    }
+
+    #[test]
+    fn backups_within_the_same_second_are_numbered() {
+        let dir = TestDir::new("backup-collision");
+        fs::write(dir.join(".env"), "").unwrap();
+        let source = backed_up_source(&dir);
+
+        let first = source.back_up("first", "20240101-000000").unwrap();
+        let second = source.back_up("second", "20240101-000000").unwrap();
+
+        assert_eq!(first, dir.join(".env.20240101-000000.bak"));
+        assert_eq!(second, dir.join(".env.20240101-000000-1.bak"));
+        assert_eq!(fs::read_to_string(first).unwrap(), "first");
+        assert_eq!(fs::read_to_string(second).unwrap(), "second");
+    }
+}
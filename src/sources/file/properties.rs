@@ -0,0 +1,219 @@
+use crate::secrets::Secrets;
+use std::str::Chars;
+
+/// Read a Java `.properties` file. Keys and values are separated by `=`, `:`
+/// or whitespace, lines starting with `#` or `!` are comments, and a line
+/// ending in a backslash continues on the next one.
+pub fn parse(text: &str) -> Secrets {
+    let mut secrets = Secrets::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let mut line = line.trim_start_matches(WHITESPACE).to_string();
+        if line.is_empty() || line.starts_with(['#', '!']) {
+            continue;
+        }
+
+        while continues(&line) {
+            line.pop();
+            match lines.next() {
+                Some(next) => line.push_str(next.trim_start_matches(WHITESPACE)),
+                None => break,
+            }
+        }
+
+        let (key, value) = split_entry(&line);
+        secrets.content.insert(unescape(key), unescape(value));
+    }
+
+    secrets
+}
+
+/// Write secrets as `key=value` lines, escaping whatever would otherwise be
+/// read back differently.
+pub fn to_string(secrets: &Secrets) -> String {
+    let mut out = String::new();
+
+    for (key, value) in &secrets.content {
+        out.push_str(&escape(key, true));
+        out.push('=');
+        out.push_str(&escape(value, false));
+        out.push('\n');
+    }
+
+    out
+}
+
+const WHITESPACE: [char; 3] = [' ', '\t', '\x0c'];
+
+/// Whether a line ends in an odd number of backslashes, the last of which is
+/// not itself escaped.
+fn continues(line: &str) -> bool {
+    line.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+fn split_entry(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    let mut key_end = line.len();
+
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' | ':' => {
+                key_end = i;
+                break;
+            }
+            c if WHITESPACE.contains(&c) => {
+                key_end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let key = &line[..key_end];
+    let mut rest = line[key_end..].trim_start_matches(WHITESPACE);
+    if let Some(after) = rest.strip_prefix(['=', ':']) {
+        rest = after.trim_start_matches(WHITESPACE);
+    }
+
+    (key, rest)
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push('\x0c'),
+            Some('u') => {
+                // Keep malformed escapes as written
+                let Some(unit) = hex_unit(&mut chars) else {
+                    out.push_str("\\u");
+                    continue;
+                };
+
+                // Characters outside the BMP are written as a surrogate pair
+                let mut units = vec![unit];
+                if (0xD800..0xDC00).contains(&unit) {
+                    let mut ahead = chars.clone();
+                    if ahead.next() == Some('\\') && ahead.next() == Some('u') {
+                        if let Some(low) =
+                            hex_unit(&mut ahead).filter(|low| (0xDC00..0xE000).contains(low))
+                        {
+                            units.push(low);
+                            chars = ahead;
+                        }
+                    }
+                }
+
+                out.extend(
+                    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                );
+            }
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+
+    out
+}
+
+/// Read the four hex digits of a `\u` escape, leaving `chars` as it was if
+/// they aren't there.
+fn hex_unit(chars: &mut Chars) -> Option<u16> {
+    let hex: String = chars.clone().take(4).collect();
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    chars.nth(3);
+    u16::from_str_radix(&hex, 16).ok()
+}
+
+/// Java reads `.properties` files as ISO-8859-1, so anything beyond ASCII is
+/// written as `\u` escapes.
+fn escape(text: &str, is_key: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\x0c' => out.push_str("\\f"),
+            // Leading whitespace of a value would be skipped on read
+            ' ' if is_key || i == 0 => out.push_str("\\ "),
+            '=' | ':' if is_key => out.push_str(&format!("\\{c}")),
+            '#' | '!' if i == 0 => out.push_str(&format!("\\{c}")),
+            c if !c.is_ascii() => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    out.push_str(&format!("\\u{unit:04X}"));
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::file::secrets;
+
+    #[test]
+    fn separators_comments_and_continuations() {
+        let text = "# comment\n! also a comment\ndb.host = localhost\ndb.port:5432\nname value\nlist=a,\\\n     b\nempty\n";
+        let secrets = parse(text);
+
+        assert_eq!(secrets.content["db.host"], "localhost");
+        assert_eq!(secrets.content["db.port"], "5432");
+        assert_eq!(secrets.content["name"], "value");
+        assert_eq!(secrets.content["list"], "a,b");
+        assert_eq!(secrets.content["empty"], "");
+        assert_eq!(secrets.content.len(), 5);
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        let secrets = parse("path=C:\\\\app\ngreeting=caf\\u00e9\\n\nkey\\ with\\=sign=1\n");
+
+        assert_eq!(secrets.content["path"], "C:\\app");
+        assert_eq!(secrets.content["greeting"], "café\n");
+        assert_eq!(secrets.content["key with=sign"], "1");
+    }
+
+    #[test]
+    fn non_ascii_is_escaped_for_java() {
+        let secrets = secrets(&[("name", "café 😀")]);
+        let text = to_string(&secrets);
+
+        assert_eq!(text, "name=caf\\u00E9 \\uD83D\\uDE00\n");
+        assert_eq!(parse(&text).content, secrets.content);
+    }
+
+    #[test]
+    fn written_values_round_trip() {
+        let secrets = secrets(&[
+            ("a key:with=separators", " leading space"),
+            ("#hash", "!bang"),
+            ("multi", "line one\nline two\\"),
+            ("plain", "value # not a comment"),
+        ]);
+
+        assert_eq!(parse(&to_string(&secrets)).content, secrets.content);
+    }
+}
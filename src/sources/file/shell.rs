@@ -0,0 +1,156 @@
+use super::{format::FileFormat, FileSourceError};
+use crate::secrets::Secrets;
+
+/// Render secrets as commands that set environment variables in a shell, or
+/// as a Docker env file. Values are quoted so that the output can be
+/// evaluated whatever they contain.
+pub fn to_string(secrets: &Secrets, format: FileFormat) -> Result<String, FileSourceError> {
+    let mut out = String::new();
+
+    for (key, value) in &secrets.content {
+        let valid = match format {
+            FileFormat::Docker => is_docker_name(key),
+            _ => is_identifier(key),
+        };
+        if !valid {
+            return Err(FileSourceError::InvalidName {
+                key: key.clone(),
+                format,
+            });
+        }
+
+        let line = match format {
+            FileFormat::Sh => format!("export {key}={}\n", quote_sh(value)),
+            FileFormat::Fish => format!("set -gx {key} {}\n", quote_fish(value)),
+            FileFormat::PowerShell => format!("$env:{key} = {}\n", quote_powershell(value)),
+            FileFormat::Docker => {
+                // Docker reads values verbatim, so there is no way to escape
+                // a line break
+                if value.contains(['\n', '\r']) {
+                    return Err(FileSourceError::MultilineValue {
+                        key: key.clone(),
+                        format,
+                    });
+                }
+                format!("{key}={value}\n")
+            }
+            _ => unreachable!("{format} is not a shell format"),
+        };
+
+        out.push_str(&line);
+    }
+
+    Ok(out)
+}
+
+/// Read a Docker env file. Lines without `=` pass a variable through from
+/// Docker's own environment and hold no value, so they are skipped.
+pub fn parse_docker(text: &str) -> Secrets {
+    let mut secrets = Secrets::new();
+
+    for line in text.lines() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once('=') {
+            secrets.content.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    secrets
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_docker_name(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('#')
+        && !key.contains(|c: char| c == '=' || c.is_whitespace())
+}
+
+/// Nothing is special inside single quotes, so a quote is written by closing
+/// the string, adding an escaped quote and opening it again.
+fn quote_sh(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// fish single quotes only treat `\\` and `\'` as escapes.
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// PowerShell escapes a quote by doubling it, and also accepts typographic
+/// single quotes as delimiters.
+fn quote_powershell(value: &str) -> String {
+    let mut quoted = String::from("'");
+
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+
+    quoted.push('\'');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::file::secrets;
+
+    #[test]
+    fn values_are_quoted_for_each_shell() {
+        let input = secrets(&[("TOKEN", r#"it's $HOME `id` \n"#)]);
+
+        assert_eq!(
+            to_string(&input, FileFormat::Sh).unwrap(),
+            "export TOKEN='it'\\''s $HOME `id` \\n'\n"
+        );
+        assert_eq!(
+            to_string(&input, FileFormat::Fish).unwrap(),
+            "set -gx TOKEN 'it\\'s $HOME `id` \\\\n'\n"
+        );
+        assert_eq!(
+            to_string(&input, FileFormat::PowerShell).unwrap(),
+            "$env:TOKEN = 'it''s $HOME `id` \\n'\n"
+        );
+    }
+
+    #[test]
+    fn keys_that_are_not_variable_names_are_rejected() {
+        for key in ["1ST", "DB-HOST", "A;rm -rf ~"] {
+            assert!(matches!(
+                to_string(&secrets(&[(key, "x")]), FileFormat::Sh),
+                Err(FileSourceError::InvalidName { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn docker_env_files_are_unquoted() {
+        let input = secrets(&[
+            ("db.url", "postgres://u:p@host/db?a=b"),
+            ("QUOTED", "\"x\""),
+        ]);
+        let text = to_string(&input, FileFormat::Docker).unwrap();
+
+        assert_eq!(text, "QUOTED=\"x\"\ndb.url=postgres://u:p@host/db?a=b\n");
+        assert_eq!(parse_docker(&text).content, input.content);
+
+        assert!(matches!(
+            to_string(&secrets(&[("CERT", "a\nb")]), FileFormat::Docker),
+            Err(FileSourceError::MultilineValue { .. })
+        ));
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
 };
 use k8s_openapi::api::core::v1::Secret as K8sSecret;
 use kube::{
-    api::{ObjectMeta, PostParams},
+    api::{ObjectMeta, Patch, PatchParams, PostParams},
     config::KubeConfigOptions,
     Api,
 };
@@ -18,6 +18,12 @@ pub enum K8sSourceError {
     #[error("Kubernetes context cannot be empty")]
     EmptyContext,
 
+    #[error("URL path must be `/<secret>` or `/<namespace>/<secret>`")]
+    InvalidPath,
+
+    #[error("namespace '{path}' in the URL path conflicts with `?namespace={query}`")]
+    ConflictingNamespace { path: String, query: String },
+
     #[error("failed to build Tokio runtime")]
     BuildRuntime(#[source] std::io::Error),
 
@@ -41,6 +47,7 @@ pub struct K8sSource {
     api: Api<K8sSecret>,
     runtime: Runtime,
     secret_name: String,
+    display_name: String,
 }
 
 impl K8sSource {
@@ -57,19 +64,57 @@ impl K8sSource {
             return Err(K8sSourceError::EmptyContext);
         }
 
-        let api = runtime.block_on(create_k8s_secrets_api(context))?;
+        let (namespace, secret_name) = parse_secret_path(url)?;
+        let display_name = match &namespace {
+            Some(namespace) => format!("{namespace}/{secret_name}"),
+            None => secret_name.clone(),
+        };
+
+        let api = runtime.block_on(create_k8s_secrets_api(context, namespace))?;
 
         Ok(K8sSource {
             api,
-            secret_name: url.path().trim_matches('/').to_string(),
+            secret_name,
+            display_name,
             runtime,
         })
     }
 }
 
+/// Split a `k8s://` URL into an optional namespace and the secret name. The
+/// namespace comes from `/<namespace>/<secret>` or a `?namespace=` parameter.
+fn parse_secret_path(url: &url::Url) -> Result<(Option<String>, String), K8sSourceError> {
+    let segments: Vec<&str> = url
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    let (path_namespace, secret_name) = match segments.as_slice() {
+        [secret_name] => (None, secret_name.to_string()),
+        [namespace, secret_name] => (Some(namespace.to_string()), secret_name.to_string()),
+        _ => return Err(K8sSourceError::InvalidPath),
+    };
+
+    let query_namespace = url
+        .query_pairs()
+        .find(|(key, _)| key == "namespace")
+        .map(|(_, value)| value.into_owned())
+        .filter(|namespace| !namespace.is_empty());
+
+    let namespace = match (path_namespace, query_namespace) {
+        (Some(path), Some(query)) if path != query => {
+            return Err(K8sSourceError::ConflictingNamespace { path, query })
+        }
+        (path, query) => path.or(query),
+    };
+
+    Ok((namespace, secret_name))
+}
+
 impl super::Source for K8sSource {
     fn read_secrets(&self) -> Result<crate::secrets::Secrets, SourceSecretsError> {
-        eprintln!("Reading secrets from k8s secret {}", self.secret_name);
+        eprintln!("Reading secrets from k8s secret {}", self.display_name);
 
         let body = self
             .runtime
@@ -77,7 +122,7 @@ impl super::Source for K8sSource {
             .map_err(K8sSourceError::Api)?;
 
         let data = body.data.ok_or_else(|| K8sSourceError::EmptySecret {
-            name: self.secret_name.clone(),
+            name: self.display_name.clone(),
         })?;
 
         let secrets = Secrets::try_from(data).map_err(K8sSourceError::Decode)?;
@@ -86,7 +131,7 @@ impl super::Source for K8sSource {
     }
 
     fn write_secrets(&self, secrets: &crate::secrets::Secrets) -> Result<(), SourceSecretsError> {
-        eprintln!("Writing secrets to k8s secret {}", self.secret_name);
+        eprintln!("Writing secrets to k8s secret {}", self.display_name);
 
         self.runtime.block_on(create_or_update_secrets(
             &self.api,
@@ -96,9 +141,47 @@ impl super::Source for K8sSource {
 
         Ok(())
     }
+
+    fn plan_write(
+        &self,
+        _secrets: &crate::secrets::Secrets,
+        merge: bool,
+    ) -> Result<super::WritePlan, SourceSecretsError> {
+        let existing_secret = self
+            .runtime
+            .block_on(self.api.get_opt(&self.secret_name))
+            .map_err(K8sSourceError::Api)?;
+
+        let exists = existing_secret.is_some();
+        let operation = match (exists, merge) {
+            (false, _) => format!("create k8s secret {}", self.display_name),
+            (true, false) => format!("replace k8s secret {}", self.display_name),
+            (true, true) => format!("strategic merge patch k8s secret {}", self.display_name),
+        };
+
+        Ok(super::WritePlan {
+            exists,
+            operations: vec![operation],
+        })
+    }
+
+    fn merge_secrets(&self, secrets: &crate::secrets::Secrets) -> Result<(), SourceSecretsError> {
+        eprintln!("Merging secrets into k8s secret {}", self.display_name);
+
+        self.runtime.block_on(create_or_patch_secrets(
+            &self.api,
+            &self.secret_name,
+            secrets,
+        ))?;
+
+        Ok(())
+    }
 }
 
-async fn create_k8s_secrets_api(context: String) -> Result<Api<K8sSecret>, K8sSourceError> {
+async fn create_k8s_secrets_api(
+    context: String,
+    namespace: Option<String>,
+) -> Result<Api<K8sSecret>, K8sSourceError> {
     let options = KubeConfigOptions {
         context: Some(context),
         ..KubeConfigOptions::default()
@@ -106,24 +189,31 @@ async fn create_k8s_secrets_api(context: String) -> Result<Api<K8sSecret>, K8sSo
 
     let config = kube::Config::from_kubeconfig(&options).await?;
     let client = kube::Client::try_from(config).map_err(K8sSourceError::Client)?;
-    let api: Api<K8sSecret> = Api::default_namespaced(client);
+    let api: Api<K8sSecret> = match namespace {
+        Some(namespace) => Api::namespaced(client, &namespace),
+        None => Api::default_namespaced(client),
+    };
 
     Ok(api)
 }
 
-async fn create_or_update_secrets(
-    api: &Api<K8sSecret>,
-    secret_name: &str,
-    secrets: &crate::secrets::Secrets,
-) -> Result<(), K8sSourceError> {
-    let payload = K8sSecret {
+fn secret_payload(secret_name: &str, secrets: &crate::secrets::Secrets) -> K8sSecret {
+    K8sSecret {
         metadata: ObjectMeta {
             name: Some(secret_name.to_string()),
             ..ObjectMeta::default()
         },
         string_data: Some(secrets.content.clone()),
         ..K8sSecret::default()
-    };
+    }
+}
+
+async fn create_or_update_secrets(
+    api: &Api<K8sSecret>,
+    secret_name: &str,
+    secrets: &crate::secrets::Secrets,
+) -> Result<(), K8sSourceError> {
+    let payload = secret_payload(secret_name, secrets);
 
     let existing_secret = api
         .get_opt(secret_name)
@@ -143,3 +233,79 @@ async fn create_or_update_secrets(
 
     Ok(())
 }
+
+/// Strategic merge patch the secret so that keys not in `secrets` are kept.
+async fn create_or_patch_secrets(
+    api: &Api<K8sSecret>,
+    secret_name: &str,
+    secrets: &crate::secrets::Secrets,
+) -> Result<(), K8sSourceError> {
+    let payload = secret_payload(secret_name, secrets);
+
+    let existing_secret = api
+        .get_opt(secret_name)
+        .await
+        .map_err(K8sSourceError::Api)?;
+
+    match existing_secret {
+        Some(_) => api
+            .patch(
+                secret_name,
+                &PatchParams::default(),
+                &Patch::Strategic(&payload),
+            )
+            .await
+            .map_err(K8sSourceError::Api)?,
+        None => api
+            .create(&PostParams::default(), &payload)
+            .await
+            .map_err(K8sSourceError::Api)?,
+    };
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(uri: &str) -> Result<(Option<String>, String), K8sSourceError> {
+        parse_secret_path(&url::Url::parse(uri).unwrap())
+    }
+
+    #[test]
+    fn secret_in_default_namespace() {
+        let (namespace, name) = parse("k8s://my-context/app-secrets").unwrap();
+        assert_eq!(namespace, None);
+        assert_eq!(name, "app-secrets");
+    }
+
+    #[test]
+    fn namespace_from_path() {
+        let (namespace, name) = parse("k8s://my-context/staging/app-secrets").unwrap();
+        assert_eq!(namespace.as_deref(), Some("staging"));
+        assert_eq!(name, "app-secrets");
+    }
+
+    #[test]
+    fn namespace_from_query() {
+        let (namespace, name) = parse("k8s://my-context/app-secrets?namespace=staging").unwrap();
+        assert_eq!(namespace.as_deref(), Some("staging"));
+        assert_eq!(name, "app-secrets");
+    }
+
+    #[test]
+    fn conflicting_namespaces() {
+        let result = parse("k8s://my-context/staging/app-secrets?namespace=prod");
+        assert!(matches!(
+            result,
+            Err(K8sSourceError::ConflictingNamespace { .. })
+        ));
+    }
+
+    #[test]
+    fn too_many_path_segments() {
+        let result = parse("k8s://my-context/a/b/c");
+        assert!(matches!(result, Err(K8sSourceError::InvalidPath)));
+    }
+}
@@ -0,0 +1,101 @@
+use super::{Source, SourceSecretsError, WritePlan};
+use crate::secrets::Secrets;
+use std::collections::BTreeMap;
+
+/// Combines several sources into one. Layers are read in order and later
+/// layers override keys from earlier ones.
+pub struct LayeredSource {
+    layers: Vec<(String, Box<dyn Source>)>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayeredSourceError {
+    #[error("unable to read layer '{uri}'")]
+    ReadLayer {
+        uri: String,
+        #[source]
+        source: Box<SourceSecretsError>,
+    },
+
+    #[error("several layered sources cannot be a target")]
+    ReadOnly,
+}
+
+impl LayeredSource {
+    pub fn new(layers: Vec<(String, Box<dyn Source>)>) -> Self {
+        Self { layers }
+    }
+}
+
+impl Source for LayeredSource {
+    fn read_secrets(&self) -> Result<Secrets, SourceSecretsError> {
+        self.read_secrets_with_layers().map(|(secrets, _)| secrets)
+    }
+
+    fn read_secrets_with_layers(
+        &self,
+    ) -> Result<(Secrets, BTreeMap<String, String>), SourceSecretsError> {
+        let mut secrets = Secrets::new();
+        let mut layer_of = BTreeMap::new();
+
+        for (uri, layer) in &self.layers {
+            let layer_secrets =
+                layer
+                    .read_secrets()
+                    .map_err(|source| LayeredSourceError::ReadLayer {
+                        uri: uri.clone(),
+                        source: Box::new(source),
+                    })?;
+
+            for key in layer_secrets.content.keys() {
+                layer_of.insert(key.clone(), uri.clone());
+            }
+            secrets.merge(&layer_secrets);
+        }
+
+        Ok((secrets, layer_of))
+    }
+
+    fn write_secrets(&self, _secrets: &Secrets) -> Result<(), SourceSecretsError> {
+        Err(LayeredSourceError::ReadOnly.into())
+    }
+
+    fn merge_secrets(&self, _secrets: &Secrets) -> Result<(), SourceSecretsError> {
+        Err(LayeredSourceError::ReadOnly.into())
+    }
+
+    fn plan_write(
+        &self,
+        _secrets: &Secrets,
+        _merge: bool,
+    ) -> Result<WritePlan, SourceSecretsError> {
+        Err(LayeredSourceError::ReadOnly.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::test_source::MemorySource;
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let source = LayeredSource::new(vec![
+            (
+                "defaults".to_string(),
+                Box::new(MemorySource::new(&[("A", "1"), ("B", "1")])),
+            ),
+            (
+                "local".to_string(),
+                Box::new(MemorySource::new(&[("B", "2")])),
+            ),
+        ]);
+
+        let (secrets, layer_of) = source.read_secrets_with_layers().unwrap();
+
+        assert_eq!(secrets.content["A"], "1");
+        assert_eq!(secrets.content["B"], "2");
+        assert_eq!(layer_of["A"], "defaults");
+        assert_eq!(layer_of["B"], "local");
+    }
+}
@@ -1,10 +1,23 @@
+use std::collections::BTreeMap;
 use url::Url;
 
+mod atomic;
+mod dir;
+mod env;
 mod file;
 mod k8s;
+mod layered;
+mod path;
 mod stdinout;
+#[cfg(test)]
+mod test_dir;
+#[cfg(test)]
+pub mod test_source;
 mod vault;
 
+pub use file::FileFormat;
+pub use path::{join_path_uri, rebase_uri};
+
 #[derive(Debug, thiserror::Error)]
 pub enum SourceCreateError {
     #[error("unsupported source scheme: {0}")]
@@ -16,6 +29,12 @@ pub enum SourceCreateError {
     #[error("unable to determine source, provide either `--{field}` or a preset")]
     NoSourceProvided { field: &'static str },
 
+    #[error("could not build directory source")]
+    Dir(#[from] dir::DirSourceError),
+
+    #[error("could not build environment source")]
+    Env(#[from] env::EnvSourceError),
+
     #[error("could not build file source")]
     File(#[from] file::FileSourceError),
 
@@ -28,37 +47,139 @@ pub enum SourceCreateError {
 
 #[derive(Debug, thiserror::Error)]
 pub enum SourceSecretsError {
+    #[error("directory error")]
+    Dir(#[from] dir::DirSourceError),
+
+    #[error("environment error")]
+    Env(#[from] env::EnvSourceError),
+
     #[error("file error")]
     File(#[from] file::FileSourceError),
 
     #[error("kubernetes error")]
     K8s(#[from] k8s::K8sSourceError),
 
+    #[error("layered source error")]
+    Layered(#[from] layered::LayeredSourceError),
+
     #[error("stdin/stdout error")]
     StdInOut(#[from] stdinout::StdInOutSourceError),
 
     #[error("vault error")]
     Vault(#[from] vault::VaultSourceError),
+
+    #[error("this source cannot list secrets recursively")]
+    ListUnsupported,
+}
+
+/// Settings, usually from a preset, that are used when building sources.
+#[derive(Debug, Default, Clone)]
+pub struct SourceOptions {
+    pub vault: crate::config::VaultConfig,
+
+    /// How `std://` and `file://` targets are written, from `--format`.
+    /// A `?format=` parameter on a file still wins.
+    pub format: Option<FileFormat>,
+
+    /// Keep a timestamped copy of `file://` targets before changing them
+    pub backup: bool,
+}
+
+/// What writing to a source would do, for dry runs.
+#[derive(Debug)]
+pub struct WritePlan {
+    /// Whether the target exists and can be read. Targets that can't are
+    /// treated as empty.
+    pub exists: bool,
+
+    /// The backend operations that a write would perform, in order
+    pub operations: Vec<String>,
 }
 
 /// Source trait for reading/writing secrets.
 pub trait Source {
     fn read_secrets(&self) -> Result<crate::secrets::Secrets, SourceSecretsError>;
     fn write_secrets(&self, secrets: &crate::secrets::Secrets) -> Result<(), SourceSecretsError>;
+
+    /// Read the secrets along with the layer each key came from. Only
+    /// sources built from several layers know this; others return no layers.
+    fn read_secrets_with_layers(
+        &self,
+    ) -> Result<(crate::secrets::Secrets, BTreeMap<String, String>), SourceSecretsError> {
+        Ok((self.read_secrets()?, BTreeMap::new()))
+    }
+
+    /// Add and update the given secrets, leaving any other existing secrets
+    /// in place. Sources should override this when they can merge natively or
+    /// when the target may not exist yet.
+    fn merge_secrets(&self, secrets: &crate::secrets::Secrets) -> Result<(), SourceSecretsError> {
+        let mut merged = self.read_secrets()?;
+        merged.merge(secrets);
+        self.write_secrets(&merged)
+    }
+
+    /// Describe what `write_secrets` (or `merge_secrets` when `merge` is set)
+    /// would do with `secrets`, without changing anything.
+    fn plan_write(
+        &self,
+        secrets: &crate::secrets::Secrets,
+        merge: bool,
+    ) -> Result<WritePlan, SourceSecretsError>;
+
+    /// List the paths of every secret below this source, relative to it.
+    /// Only sources that can hold a tree of secrets support this.
+    fn list_secrets(&self) -> Result<Vec<String>, SourceSecretsError> {
+        Err(SourceSecretsError::ListUnsupported)
+    }
+
+    /// A source for the secret at `path` below this one that reuses this
+    /// source's connection and login. Sources that are cheap to build from a
+    /// URI don't need to support this.
+    fn child(&self, _path: &str) -> Option<Box<dyn Source>> {
+        None
+    }
 }
 
 impl dyn Source {
-    pub fn new(uri: &str) -> Result<Box<dyn Source>, SourceCreateError> {
+    pub fn new(uri: &str, options: &SourceOptions) -> Result<Box<dyn Source>, SourceCreateError> {
+        // File paths are taken as written, since parsing them as URLs
+        // would treat the first segment as a host and drop `..` segments
+        if let Some(location) = uri.strip_prefix("file://") {
+            return Ok(Box::new(file::FileSource::new(location, options)?));
+        }
+        if let Some(location) = uri.strip_prefix("dir://") {
+            return Ok(Box::new(dir::DirSource::new(location)?));
+        }
+
         let url = Url::parse(uri)?;
 
         let source: Box<dyn Source> = match url.scheme() {
-            "file" => Box::new(file::FileSource::new(&url)?),
+            "env" => Box::new(env::EnvSource::new(&url)?),
             "k8s" | "kubernetes" => Box::new(k8s::K8sSource::new(&url)?),
-            "std" => Box::new(stdinout::StdInOutSource::new()),
-            "vault" => Box::new(vault::VaultSource::new(&url)?),
+            "std" => Box::new(stdinout::StdInOutSource::new(
+                options.format.unwrap_or(FileFormat::Env),
+            )),
+            "vault" => Box::new(vault::VaultSource::new(&url, &options.vault)?),
             other => return Err(SourceCreateError::UnsupportedScheme(other.to_string())),
         };
 
         Ok(source)
     }
+
+    /// Build a source from one URI, or layer several in order of precedence.
+    pub fn layered(
+        uris: &[String],
+        options: &SourceOptions,
+    ) -> Result<Box<dyn Source>, SourceCreateError> {
+        if let [uri] = uris {
+            return <dyn Source>::new(uri, options);
+        }
+
+        let layers = uris
+            .iter()
+            .map(|uri| Ok((uri.clone(), <dyn Source>::new(uri, options)?)))
+            .collect::<Result<_, SourceCreateError>>()?;
+
+        Ok(Box::new(layered::LayeredSource::new(layers)))
+    }
 }
@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+
+/// Schemes whose URIs hold a local path rather than a URL.
+const PATH_SCHEMES: [&str; 2] = ["file://", "dir://"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum PathError {
+    #[error("the path is empty")]
+    Empty,
+
+    #[error("unable to find the home directory")]
+    NoHomeDir,
+}
+
+/// Split the text after `file://` or `dir://` into the path and the query
+/// string, if any.
+/// The path is used as written rather than parsed as a URL, which would
+/// treat its first segment as a host and drop `..` segments.
+pub fn split_location(location: &str) -> (&str, Option<&str>) {
+    match location.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (location, None),
+    }
+}
+
+/// Turn the path of a `file://` or `dir://` URI into a file system path. Besides
+/// absolute and relative paths, `~` is the home directory and Windows paths
+/// such as `C:/app/.env` or `/C:/app/.env` are accepted.
+pub fn resolve(path: &str) -> Result<PathBuf, PathError> {
+    if path.is_empty() {
+        return Err(PathError::Empty);
+    }
+
+    if let Some(rest) = home_relative(path) {
+        let home = dirs::home_dir().ok_or(PathError::NoHomeDir)?;
+        return Ok(home.join(rest));
+    }
+
+    // `file:///C:/app` leaves a slash in front of the drive letter
+    match path.strip_prefix('/') {
+        Some(rest) if has_drive(rest) => Ok(PathBuf::from(rest)),
+        _ => Ok(PathBuf::from(path)),
+    }
+}
+
+/// Whether the path is relative to the working directory, as opposed to
+/// absolute or relative to the home directory.
+pub fn is_relative(path: &str) -> bool {
+    !(path.starts_with(['/', '\\']) || has_drive(path) || home_relative(path).is_some())
+}
+
+/// Rewrite a relative `file://` or `dir://` URI to be relative to `base`
+/// instead of the working directory. Other URIs are returned unchanged.
+pub fn rebase_uri(uri: &str, base: &Path) -> String {
+    let Some((scheme, location)) = PATH_SCHEMES
+        .iter()
+        .find_map(|scheme| Some((scheme, uri.strip_prefix(scheme)?)))
+    else {
+        return uri.to_string();
+    };
+
+    let (path, query) = split_location(location);
+    if path.is_empty() || !is_relative(path) {
+        return uri.to_string();
+    }
+
+    let mut rebased = format!("{scheme}{}", base.join(path).display());
+    if let Some(query) = query {
+        rebased.push('?');
+        rebased.push_str(query);
+    }
+
+    rebased
+}
+
+/// Append `name` to the path of a `file://` or `dir://` URI, keeping its
+/// query. Returns `None` for other URIs.
+pub fn join_path_uri(uri: &str, name: &str) -> Option<String> {
+    let (scheme, location) = PATH_SCHEMES
+        .iter()
+        .find_map(|scheme| Some((scheme, uri.strip_prefix(scheme)?)))?;
+
+    let (path, query) = split_location(location);
+    let mut joined = match path.trim_end_matches(['/', '\\']) {
+        "" => format!("{scheme}{name}"),
+        path => format!("{scheme}{path}/{name}"),
+    };
+    if let Some(query) = query {
+        joined.push('?');
+        joined.push_str(query);
+    }
+
+    Some(joined)
+}
+
+fn home_relative(path: &str) -> Option<&str> {
+    match path {
+        "~" => Some(""),
+        _ => path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")),
+    }
+}
+
+fn has_drive(path: &str) -> bool {
+    let bytes = path.as_bytes();
+
+    bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes.len() == 2 || matches!(bytes[2], b'/' | b'\\'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_kept_as_written() {
+        assert_eq!(resolve(".env").unwrap(), PathBuf::from(".env"));
+        assert_eq!(
+            resolve("../../shared/.env").unwrap(),
+            PathBuf::from("../../shared/.env")
+        );
+        assert_eq!(
+            resolve("/etc/app/.env").unwrap(),
+            PathBuf::from("/etc/app/.env")
+        );
+    }
+
+    #[test]
+    fn home_is_expanded() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(resolve("~/app/.env").unwrap(), home.join("app/.env"));
+        assert_eq!(resolve("~").unwrap(), home.join(""));
+    }
+
+    #[test]
+    fn windows_drives() {
+        assert_eq!(
+            resolve("C:/app/.env").unwrap(),
+            PathBuf::from("C:/app/.env")
+        );
+        assert_eq!(
+            resolve("/C:/app/.env").unwrap(),
+            PathBuf::from("C:/app/.env")
+        );
+        assert!(!is_relative("C:\\app\\.env"));
+    }
+
+    #[test]
+    fn empty_path_is_invalid() {
+        assert!(matches!(resolve(""), Err(PathError::Empty)));
+    }
+
+    #[test]
+    fn only_relative_uris_are_rebased() {
+        let base = Path::new("/project");
+        assert_eq!(
+            rebase_uri("file://api/.env?format=json", base),
+            "file:///project/api/.env?format=json"
+        );
+        assert_eq!(rebase_uri("file:///etc/.env", base), "file:///etc/.env");
+        assert_eq!(rebase_uri("file://~/.env", base), "file://~/.env");
+        assert_eq!(rebase_uri("dir://secrets", base), "dir:///project/secrets");
+        assert_eq!(rebase_uri("vault://secret/app", base), "vault://secret/app");
+    }
+
+    #[test]
+    fn path_uris_are_joined_as_written() {
+        assert_eq!(
+            join_path_uri("file://../out/?format=json", "db").unwrap(),
+            "file://../out/db?format=json"
+        );
+        assert_eq!(
+            join_path_uri("dir://~/secrets", "db").unwrap(),
+            "dir://~/secrets/db"
+        );
+        assert_eq!(join_path_uri("vault://secret/app", "db"), None);
+    }
+}
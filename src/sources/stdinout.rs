@@ -1,19 +1,27 @@
+use super::file::{FileFormat, FileSourceError, DEFAULT_SEPARATOR};
 use crate::secrets::Secrets;
+use std::io::Write;
 
-pub struct StdInOutSource;
+pub struct StdInOutSource {
+    /// How secrets are printed to stdout. Stdin is always read as dotenv.
+    format: FileFormat,
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum StdInOutSourceError {
     #[error("unable to pipe secrets from stdin")]
     StdIn(#[source] crate::secrets::SecretsError),
 
+    #[error("unable to format secrets for stdout")]
+    Format(#[source] FileSourceError),
+
     #[error("unable to pipe secrets to stdout")]
-    StdOut(#[source] crate::secrets::SecretsError),
+    StdOut(#[source] std::io::Error),
 }
 
 impl StdInOutSource {
-    pub fn new() -> Self {
-        StdInOutSource
+    pub fn new(format: FileFormat) -> Self {
+        StdInOutSource { format }
     }
 }
 
@@ -29,10 +37,34 @@ impl super::Source for StdInOutSource {
         &self,
         secrets: &crate::secrets::Secrets,
     ) -> Result<(), super::SourceSecretsError> {
-        secrets
-            .to_writer(&mut std::io::stdout())
+        let text = self
+            .format
+            .write(secrets, DEFAULT_SEPARATOR)
+            .map_err(StdInOutSourceError::Format)?;
+
+        std::io::stdout()
+            .write_all(text.as_bytes())
             .map_err(StdInOutSourceError::StdOut)?;
 
         Ok(())
     }
+
+    fn plan_write(
+        &self,
+        _secrets: &Secrets,
+        _merge: bool,
+    ) -> Result<super::WritePlan, super::SourceSecretsError> {
+        Ok(super::WritePlan {
+            exists: false,
+            operations: vec!["print secrets to stdout".to_string()],
+        })
+    }
+
+    /// There is nothing on stdout to merge with, so this is a plain write.
+    fn merge_secrets(
+        &self,
+        secrets: &crate::secrets::Secrets,
+    ) -> Result<(), super::SourceSecretsError> {
+        self.write_secrets(secrets)
+    }
 }
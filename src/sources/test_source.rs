@@ -0,0 +1,51 @@
+use super::{Source, SourceSecretsError, WritePlan};
+use crate::secrets::Secrets;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// A source held in memory. Clones share the same secrets, so a test can
+/// hand one to a job and check what was written through another. Each child
+/// holds a single key named after its path.
+#[derive(Clone, Default)]
+pub struct MemorySource(Rc<RefCell<BTreeMap<String, String>>>);
+
+impl MemorySource {
+    pub fn new(pairs: &[(&str, &str)]) -> Self {
+        let content = pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        MemorySource(Rc::new(RefCell::new(content)))
+    }
+
+    pub fn secrets(&self) -> BTreeMap<String, String> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Source for MemorySource {
+    fn read_secrets(&self) -> Result<Secrets, SourceSecretsError> {
+        Ok(Secrets::from(self.secrets()))
+    }
+
+    fn write_secrets(&self, secrets: &Secrets) -> Result<(), SourceSecretsError> {
+        *self.0.borrow_mut() = secrets.content.clone();
+        Ok(())
+    }
+
+    fn plan_write(
+        &self,
+        _secrets: &Secrets,
+        _merge: bool,
+    ) -> Result<WritePlan, SourceSecretsError> {
+        Ok(WritePlan {
+            exists: true,
+            operations: Vec::new(),
+        })
+    }
+
+    fn child(&self, path: &str) -> Option<Box<dyn Source>> {
+        Some(Box::new(MemorySource::new(&[(path, "x")])))
+    }
+}
@@ -0,0 +1,233 @@
+use super::VaultSourceError;
+use crate::config::VaultAuthConfig;
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use ureq::Agent;
+
+const SERVICE_ACCOUNT_TOKEN_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// Obtain a client token using the configured auth method.
+pub fn resolve_token(
+    client: &Agent,
+    addr: &str,
+    namespace: Option<&str>,
+    auth: &VaultAuthConfig,
+) -> Result<String, VaultSourceError> {
+    match auth {
+        VaultAuthConfig::Token => find_token(),
+
+        VaultAuthConfig::AppRole {
+            role_id,
+            secret_id,
+            mount,
+        } => {
+            let role_id = setting(role_id, "approle", "role_id", "VAULT_ROLE_ID")?;
+            let secret_id = setting(secret_id, "approle", "secret_id", "VAULT_SECRET_ID")?;
+            let url = login_url(addr, mount.as_deref().unwrap_or("approle"), None);
+
+            login(
+                client,
+                namespace,
+                "approle",
+                &url,
+                json!({ "role_id": role_id, "secret_id": secret_id }),
+            )
+        }
+
+        VaultAuthConfig::Kubernetes {
+            role,
+            jwt_path,
+            mount,
+        } => {
+            let jwt = read_jwt(jwt_path.as_deref().unwrap_or(SERVICE_ACCOUNT_TOKEN_PATH))?;
+            let url = login_url(addr, mount.as_deref().unwrap_or("kubernetes"), None);
+
+            login(
+                client,
+                namespace,
+                "kubernetes",
+                &url,
+                json!({ "role": role, "jwt": jwt }),
+            )
+        }
+
+        VaultAuthConfig::Userpass {
+            username,
+            password,
+            mount,
+        } => {
+            let password = setting(password, "userpass", "password", "VAULT_PASSWORD")?;
+            let url = login_url(addr, mount.as_deref().unwrap_or("userpass"), Some(username));
+
+            login(
+                client,
+                namespace,
+                "userpass",
+                &url,
+                json!({ "password": password }),
+            )
+        }
+
+        VaultAuthConfig::Jwt {
+            role,
+            jwt_path,
+            mount,
+        } => {
+            let jwt = match jwt_path {
+                Some(path) => read_jwt(path)?,
+                None => setting(&None, "jwt", "jwt_path", "VAULT_JWT")?,
+            };
+            let url = login_url(addr, mount.as_deref().unwrap_or("jwt"), None);
+
+            login(
+                client,
+                namespace,
+                "jwt",
+                &url,
+                json!({ "role": role, "jwt": jwt }),
+            )
+        }
+    }
+}
+
+// Prioritize the VAULT_TOKEN environment variable. But fall back to reading from ~/.vault-token
+fn find_token() -> Result<String, VaultSourceError> {
+    if let Ok(token) = env::var("VAULT_TOKEN") {
+        return Ok(token);
+    }
+
+    let home_dir = dirs::home_dir().ok_or(VaultSourceError::NoHomeDir)?;
+    let token_path = home_dir.join(".vault-token");
+    let token =
+        std::fs::read_to_string(&token_path).map_err(|source| VaultSourceError::ReadToken {
+            path: token_path.display().to_string(),
+            source,
+        })?;
+
+    Ok(token.trim().to_string())
+}
+
+/// Use a value from the preset, falling back to an environment variable so
+/// that credentials don't have to be committed to the config file.
+fn setting(
+    value: &Option<String>,
+    method: &'static str,
+    field: &'static str,
+    env_var: &'static str,
+) -> Result<String, VaultSourceError> {
+    if let Some(value) = value {
+        return Ok(value.clone());
+    }
+
+    env::var(env_var).map_err(|_| VaultSourceError::MissingAuthSetting {
+        method,
+        field,
+        env_var,
+    })
+}
+
+fn read_jwt(path: &str) -> Result<String, VaultSourceError> {
+    let jwt = std::fs::read_to_string(path).map_err(|source| VaultSourceError::ReadToken {
+        path: path.to_string(),
+        source,
+    })?;
+
+    Ok(jwt.trim().to_string())
+}
+
+fn login_url(addr: &str, mount: &str, username: Option<&str>) -> String {
+    let mount = mount.trim_matches('/');
+
+    match username {
+        Some(username) => format!("{addr}/v1/auth/{mount}/login/{username}"),
+        None => format!("{addr}/v1/auth/{mount}/login"),
+    }
+}
+
+fn login(
+    client: &Agent,
+    namespace: Option<&str>,
+    method: &'static str,
+    url: &str,
+    body: serde_json::Value,
+) -> Result<String, VaultSourceError> {
+    eprintln!("Logging in to Vault with the {method} auth method");
+
+    let body = serde_json::to_string(&body).map_err(VaultSourceError::Encode)?;
+
+    let mut request = client.post(url).set("Content-Type", "application/json");
+
+    if let Some(namespace) = namespace {
+        request = request.set("X-Vault-Namespace", namespace);
+    }
+
+    let body = request
+        .send_string(&body)
+        .map_err(|source| VaultSourceError::Login {
+            method,
+            source: Box::new(source),
+        })?
+        .into_reader();
+
+    let body: LoginResponse = serde_json::from_reader(body).map_err(VaultSourceError::Decode)?;
+
+    Ok(body.auth.client_token)
+}
+
+/// The parts of a login response needed to make further requests.
+#[derive(Debug, Deserialize)]
+struct LoginResponse {
+    auth: LoginAuth,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginAuth {
+    client_token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_url_uses_mount() {
+        let url = login_url("http://vault:8200", "approle", None);
+        assert_eq!(url, "http://vault:8200/v1/auth/approle/login");
+    }
+
+    #[test]
+    fn login_url_trims_mount_slashes() {
+        let url = login_url("http://vault:8200", "/k8s/prod/", None);
+        assert_eq!(url, "http://vault:8200/v1/auth/k8s/prod/login");
+    }
+
+    #[test]
+    fn userpass_login_url_includes_username() {
+        let url = login_url("http://vault:8200", "userpass", Some("alice"));
+        assert_eq!(url, "http://vault:8200/v1/auth/userpass/login/alice");
+    }
+
+    #[test]
+    fn setting_prefers_preset_value() {
+        let value = setting(&Some("id".to_string()), "approle", "role_id", "UNSET_VAR");
+        assert_eq!(value.unwrap(), "id");
+    }
+
+    #[test]
+    fn setting_reports_missing_value() {
+        let value = setting(
+            &None,
+            "approle",
+            "role_id",
+            "SCRTSYNC_TEST_DEFINITELY_UNSET",
+        );
+        assert!(matches!(
+            value,
+            Err(VaultSourceError::MissingAuthSetting {
+                field: "role_id",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,653 @@
+use crate::config::VaultConfig;
+use crate::secrets::{NestedValues, Secrets};
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::rc::Rc;
+use ureq::{Agent, AgentBuilder};
+
+mod auth;
+mod tls;
+
+/// The version of the KV secrets engine backing a mount.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum KvVersion {
+    V1,
+    V2,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultSourceError {
+    #[error("Vault URL missing host")]
+    MissingHost,
+
+    #[error("Vault URL host cannot be empty")]
+    EmptyHost,
+
+    #[error("Vault address not set, provide `?addr=` in the URL, `vault.address` in the preset or the VAULT_ADDR environment variable")]
+    MissingVaultAddr,
+
+    #[error("invalid KV engine version '{0}', expected 1 or 2")]
+    InvalidKvVersion(String),
+
+    #[error("invalid secret version '{0}'")]
+    InvalidSecretVersion(String),
+
+    #[error("reading a specific secret version requires a KV version 2 mount")]
+    SecretVersionRequiresKv2,
+
+    #[error("cannot write secrets when a `secret_version` is selected")]
+    WriteSecretVersion,
+
+    #[error("unable to detect KV engine version for mount '{mount}', try adding `?version=1` or `?version=2` to the URL")]
+    DetectKvVersion {
+        mount: String,
+        #[source]
+        source: Box<VaultSourceError>,
+    },
+
+    #[error("could not determine home directory for ~/.vault-token")]
+    NoHomeDir,
+
+    #[error("unable to read token from {path}")]
+    ReadToken {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("vault {method} login requires `{field}` in the preset or the {env_var} environment variable")]
+    MissingAuthSetting {
+        method: &'static str,
+        field: &'static str,
+        env_var: &'static str,
+    },
+
+    #[error("unable to log in to Vault with the {method} auth method")]
+    Login {
+        method: &'static str,
+        #[source]
+        source: Box<ureq::Error>,
+    },
+
+    #[error("unable to read PEM data from {path}")]
+    InvalidPem {
+        path: String,
+        #[source]
+        source: rustls::pki_types::pem::Error,
+    },
+
+    #[error("unable to read CA certificates from {path}")]
+    ReadCaPath {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("a client certificate and client key must be provided together")]
+    IncompleteClientCertificate,
+
+    #[error("invalid TLS configuration")]
+    Tls(#[source] rustls::Error),
+
+    #[error("invalid nested value strategy '{0}', expected `json` or `flatten`")]
+    InvalidNestedValues(String),
+
+    #[error("unable to convert secret values")]
+    Convert(#[source] crate::secrets::SecretsError),
+
+    #[error("unable to decode payload")]
+    Decode(#[source] serde_json::Error),
+
+    #[error("unable to encode payload")]
+    Encode(#[source] serde_json::Error),
+
+    #[error("network error")]
+    Network(Box<ureq::Error>),
+}
+
+impl VaultSourceError {
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Network(err) if matches!(**err, ureq::Error::Status(404, _)))
+    }
+}
+
+impl From<ureq::Error> for VaultSourceError {
+    fn from(e: ureq::Error) -> Self {
+        Self::Network(Box::new(e))
+    }
+}
+
+pub struct VaultSource {
+    client: Agent,
+    addr: String,
+    namespace: Option<String>,
+    token: String,
+    mount_path: String,
+    secret_path: String,
+    /// Shared with child sources, which are on the same mount
+    kv_version: Rc<OnceCell<KvVersion>>,
+    secret_version: Option<u64>,
+    nested: NestedValues,
+    separator: String,
+}
+
+impl VaultSource {
+    pub fn new(url: &url::Url, config: &VaultConfig) -> Result<Self, VaultSourceError> {
+        let host = url.host().ok_or(VaultSourceError::MissingHost)?;
+        let mount_path = host.to_string();
+
+        if mount_path.is_empty() {
+            return Err(VaultSourceError::EmptyHost);
+        }
+
+        let (kv_version, secret_version) = parse_version_hints(url)?;
+
+        let mut builder = AgentBuilder::new()
+            .timeout_read(std::time::Duration::from_secs(5))
+            .timeout_write(std::time::Duration::from_secs(5));
+
+        if let Some(tls_config) = tls::client_config(&config.tls)? {
+            builder = builder.tls_config(tls_config);
+        }
+
+        let client = builder.build();
+
+        // Settings in the URL win over the preset, which wins over the environment.
+        let addr = query_value(url, "addr")
+            .or_else(|| config.address.clone())
+            .or_else(|| env::var("VAULT_ADDR").ok())
+            .filter(|addr| !addr.is_empty())
+            .ok_or(VaultSourceError::MissingVaultAddr)?
+            .trim_end_matches('/')
+            .to_string();
+
+        let namespace = query_value(url, "namespace")
+            .or_else(|| config.namespace.clone())
+            .or_else(|| env::var("VAULT_NAMESPACE").ok())
+            .filter(|namespace| !namespace.is_empty());
+
+        let nested = match query_value(url, "nested") {
+            Some(value) => match value.as_str() {
+                "json" => NestedValues::Json,
+                "flatten" => NestedValues::Flatten,
+                _ => return Err(VaultSourceError::InvalidNestedValues(value)),
+            },
+            None => config.nested,
+        };
+
+        let separator = query_value(url, "separator")
+            .or_else(|| config.separator.clone())
+            .unwrap_or_else(|| "_".to_string());
+
+        let token = auth::resolve_token(&client, &addr, namespace.as_deref(), &config.auth)?;
+
+        Ok(VaultSource {
+            mount_path,
+            secret_path: url.path().to_string(),
+            kv_version: Rc::new(kv_version.map(OnceCell::from).unwrap_or_default()),
+            secret_version,
+            nested,
+            separator,
+            client,
+            addr,
+            namespace,
+            token,
+        })
+    }
+
+    /// Start a request carrying the token and, when set, the namespace.
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = self
+            .client
+            .request(method, url)
+            .set("X-Vault-Token", &self.token);
+
+        match &self.namespace {
+            Some(namespace) => request.set("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    // Generate the full URL to read and write secrets.
+    fn url(&self) -> Result<String, VaultSourceError> {
+        Ok(secret_url(
+            &self.addr,
+            &self.mount_path,
+            &self.secret_path,
+            self.kv_version()?,
+        ))
+    }
+
+    /// Use the `?version=` hint when given, otherwise ask Vault which
+    /// version of the KV engine is mounted. The answer is cached.
+    fn kv_version(&self) -> Result<KvVersion, VaultSourceError> {
+        if let Some(version) = self.kv_version.get() {
+            return Ok(*version);
+        }
+
+        let version =
+            self.detect_kv_version()
+                .map_err(|source| VaultSourceError::DetectKvVersion {
+                    mount: self.mount_path.clone(),
+                    source: Box::new(source),
+                })?;
+
+        if self.secret_version.is_some() && version == KvVersion::V1 {
+            return Err(VaultSourceError::SecretVersionRequiresKv2);
+        }
+
+        Ok(*self.kv_version.get_or_init(|| version))
+    }
+
+    fn detect_kv_version(&self) -> Result<KvVersion, VaultSourceError> {
+        let url = format!(
+            "{}/v1/sys/internal/ui/mounts/{}",
+            self.addr, self.mount_path
+        );
+
+        let body = self.request("GET", &url).call()?.into_reader();
+
+        let body: MountResponse =
+            serde_json::from_reader(body).map_err(VaultSourceError::Decode)?;
+
+        let version = body
+            .data
+            .options
+            .and_then(|options| options.version)
+            .unwrap_or_default();
+
+        Ok(match version.as_str() {
+            "2" => KvVersion::V2,
+            _ => KvVersion::V1,
+        })
+    }
+}
+
+fn query_value(url: &url::Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Read the `?version=` (KV engine version) and `?secret_version=` (version of
+/// the secret to read) query parameters from a `vault://` URL.
+fn parse_version_hints(
+    url: &url::Url,
+) -> Result<(Option<KvVersion>, Option<u64>), VaultSourceError> {
+    let mut kv_version = None;
+    let mut secret_version = None;
+
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "version" => {
+                kv_version = Some(match value.as_ref() {
+                    "1" => KvVersion::V1,
+                    "2" => KvVersion::V2,
+                    other => return Err(VaultSourceError::InvalidKvVersion(other.to_string())),
+                });
+            }
+            "secret_version" => {
+                let version = value
+                    .parse()
+                    .map_err(|_| VaultSourceError::InvalidSecretVersion(value.to_string()))?;
+                secret_version = Some(version);
+            }
+            _ => {}
+        }
+    }
+
+    if secret_version.is_some() && kv_version == Some(KvVersion::V1) {
+        return Err(VaultSourceError::SecretVersionRequiresKv2);
+    }
+
+    Ok((kv_version, secret_version))
+}
+
+/// Build the URL of a secret. KV version 2 mounts nest secrets under `/data/`.
+fn secret_url(addr: &str, mount_path: &str, secret_path: &str, kv_version: KvVersion) -> String {
+    match kv_version {
+        KvVersion::V1 => format!("{addr}/v1/{mount_path}{secret_path}"),
+        KvVersion::V2 => format!("{addr}/v1/{mount_path}/data{secret_path}"),
+    }
+}
+
+/// Build the URL used to LIST the secrets below a path. KV version 2 mounts
+/// list through `/metadata/`.
+fn list_url(addr: &str, mount_path: &str, path: &str, kv_version: KvVersion) -> String {
+    match kv_version {
+        KvVersion::V1 => format!("{addr}/v1/{mount_path}{path}"),
+        KvVersion::V2 => format!("{addr}/v1/{mount_path}/metadata{path}"),
+    }
+}
+
+impl VaultSource {
+    /// List the leaf secrets below `path`, descending into every folder.
+    /// Returned paths are relative to `self.secret_path`.
+    fn list_recursive(&self, path: &str) -> Result<Vec<String>, VaultSourceError> {
+        let url = list_url(&self.addr, &self.mount_path, path, self.kv_version()?);
+
+        let body = self.request("LIST", &url).call()?.into_reader();
+        let body: SecretResponse<ListData> =
+            serde_json::from_reader(body).map_err(VaultSourceError::Decode)?;
+
+        let base = self.secret_path.trim_end_matches('/');
+        let mut leaves = Vec::new();
+
+        for key in body.data.keys {
+            let child = format!("{path}{key}");
+
+            if key.ends_with('/') {
+                leaves.extend(self.list_recursive(&child)?);
+            } else {
+                let relative = child[base.len()..].trim_start_matches('/');
+                leaves.push(relative.to_string());
+            }
+        }
+
+        Ok(leaves)
+    }
+}
+
+impl super::Source for VaultSource {
+    fn read_secrets(&self) -> Result<crate::secrets::Secrets, super::SourceSecretsError> {
+        let url = self.url()?;
+        eprintln!("Reading secrets from Vault at {url}");
+
+        let mut request = self
+            .request("GET", &url)
+            .set("Content-Type", "application/json");
+
+        if let Some(version) = self.secret_version {
+            request = request.query("version", &version.to_string());
+        }
+
+        let body = request
+            .call()
+            .map_err(VaultSourceError::from)?
+            .into_reader();
+
+        let data = match self.kv_version()? {
+            KvVersion::V1 => {
+                let body: SecretResponse<BTreeMap<String, Value>> =
+                    serde_json::from_reader(body).map_err(VaultSourceError::Decode)?;
+                body.data
+            }
+            KvVersion::V2 => {
+                let body: SecretResponse<KvV2Data> =
+                    serde_json::from_reader(body).map_err(VaultSourceError::Decode)?;
+                body.data.data
+            }
+        };
+
+        let (secrets, lossy) = Secrets::from_json_map(data, self.nested, &self.separator)
+            .map_err(VaultSourceError::Convert)?;
+
+        for value in lossy {
+            eprintln!("Warning: {value}");
+        }
+
+        Ok(secrets)
+    }
+
+    fn write_secrets(
+        &self,
+        secrets: &crate::secrets::Secrets,
+    ) -> Result<(), super::SourceSecretsError> {
+        if self.secret_version.is_some() {
+            return Err(VaultSourceError::WriteSecretVersion.into());
+        }
+
+        let url = self.url()?;
+        eprintln!("Writing secrets to Vault at {url}");
+
+        let body = match self.kv_version()? {
+            KvVersion::V1 => serde_json::to_string(&secrets.content),
+            KvVersion::V2 => serde_json::to_string(&KvV2Write {
+                data: &secrets.content,
+            }),
+        }
+        .map_err(VaultSourceError::Encode)?;
+
+        self.request("PUT", &url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+            .map_err(VaultSourceError::from)?;
+
+        Ok(())
+    }
+
+    fn merge_secrets(
+        &self,
+        secrets: &crate::secrets::Secrets,
+    ) -> Result<(), super::SourceSecretsError> {
+        if self.secret_version.is_some() {
+            return Err(VaultSourceError::WriteSecretVersion.into());
+        }
+
+        match self.kv_version()? {
+            KvVersion::V2 => {
+                let url = self.url()?;
+                eprintln!("Merging secrets into Vault at {url}");
+
+                let body = serde_json::to_string(&KvV2Write {
+                    data: &secrets.content,
+                })
+                .map_err(VaultSourceError::Encode)?;
+
+                let result = self
+                    .request("PATCH", &url)
+                    .set("Content-Type", "application/merge-patch+json")
+                    .send_string(&body);
+
+                match result {
+                    Ok(_) => Ok(()),
+                    // PATCH only works on secrets that already exist
+                    Err(ureq::Error::Status(404, _)) => self.write_secrets(secrets),
+                    Err(err) => Err(VaultSourceError::from(err).into()),
+                }
+            }
+            KvVersion::V1 => {
+                // KV version 1 cannot PATCH, so read, merge and write everything back.
+                let mut merged = match self.read_secrets() {
+                    Ok(existing) => existing,
+                    Err(super::SourceSecretsError::Vault(err)) if err.is_not_found() => {
+                        Secrets::new()
+                    }
+                    Err(err) => return Err(err),
+                };
+                merged.merge(secrets);
+                self.write_secrets(&merged)
+            }
+        }
+    }
+
+    fn plan_write(
+        &self,
+        _secrets: &Secrets,
+        merge: bool,
+    ) -> Result<super::WritePlan, super::SourceSecretsError> {
+        if self.secret_version.is_some() {
+            return Err(VaultSourceError::WriteSecretVersion.into());
+        }
+
+        let url = self.url()?;
+        let exists = match self.request("GET", &url).call() {
+            Ok(_) => true,
+            Err(ureq::Error::Status(404, _)) => false,
+            Err(err) => return Err(VaultSourceError::from(err).into()),
+        };
+
+        let operations = match (self.kv_version()?, exists, merge) {
+            (KvVersion::V2, true, true) => vec![format!("PATCH {url}")],
+            (KvVersion::V1, true, true) => vec![format!("GET {url}"), format!("PUT {url}")],
+            _ => vec![format!("PUT {url}")],
+        };
+
+        Ok(super::WritePlan { exists, operations })
+    }
+
+    /// Secrets below this one share its client, token and mount, so
+    /// recursive syncs log in and detect the KV version only once.
+    fn child(&self, path: &str) -> Option<Box<dyn super::Source>> {
+        Some(Box::new(VaultSource {
+            client: self.client.clone(),
+            addr: self.addr.clone(),
+            namespace: self.namespace.clone(),
+            token: self.token.clone(),
+            mount_path: self.mount_path.clone(),
+            secret_path: format!("{}/{path}", self.secret_path.trim_end_matches('/')),
+            kv_version: Rc::clone(&self.kv_version),
+            secret_version: self.secret_version,
+            nested: self.nested,
+            separator: self.separator.clone(),
+        }))
+    }
+
+    fn list_secrets(&self) -> Result<Vec<String>, super::SourceSecretsError> {
+        let path = format!("{}/", self.secret_path.trim_end_matches('/'));
+        eprintln!(
+            "Listing secrets in Vault at {}",
+            list_url(&self.addr, &self.mount_path, &path, self.kv_version()?)
+        );
+
+        let mut leaves = self.list_recursive(&path)?;
+        leaves.sort();
+
+        Ok(leaves)
+    }
+}
+
+/// The shape of the response when fetching Secrets.
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretResponse<T> {
+    #[serde(rename = "request_id")]
+    pub request_id: String,
+    #[serde(rename = "lease_id")]
+    pub lease_id: String,
+    pub renewable: bool,
+    #[serde(rename = "lease_duration")]
+    pub lease_duration: i64,
+    pub data: T,
+    #[serde(rename = "wrap_info")]
+    pub wrap_info: Value,
+    pub warnings: Value,
+    pub auth: Value,
+}
+
+/// The `data` of a KV version 2 read, which nests the secrets one level deeper.
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct KvV2Data {
+    pub data: BTreeMap<String, Value>,
+    pub metadata: Value,
+}
+
+/// The body of a KV version 2 write.
+#[derive(Debug, Serialize)]
+struct KvV2Write<'a> {
+    data: &'a BTreeMap<String, String>,
+}
+
+/// The `data` of a LIST response. Folders end with a `/`.
+#[derive(Debug, Deserialize)]
+struct ListData {
+    keys: Vec<String>,
+}
+
+/// The parts of `sys/internal/ui/mounts/:path` needed to detect the KV version.
+#[derive(Debug, Deserialize)]
+struct MountResponse {
+    data: MountData,
+}
+
+#[derive(Debug, Deserialize)]
+struct MountData {
+    options: Option<MountOptions>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MountOptions {
+    version: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_v1_url() {
+        let url = secret_url("http://vault:8200", "secret", "/app/db", KvVersion::V1);
+        assert_eq!(url, "http://vault:8200/v1/secret/app/db");
+    }
+
+    #[test]
+    fn kv_v2_url_uses_data_path() {
+        let url = secret_url("http://vault:8200", "secret", "/app/db", KvVersion::V2);
+        assert_eq!(url, "http://vault:8200/v1/secret/data/app/db");
+    }
+
+    #[test]
+    fn kv_v1_list_url() {
+        let url = list_url("http://vault:8200", "secret", "/team/app/", KvVersion::V1);
+        assert_eq!(url, "http://vault:8200/v1/secret/team/app/");
+    }
+
+    #[test]
+    fn kv_v2_list_url_uses_metadata_path() {
+        let url = list_url("http://vault:8200", "secret", "/team/app/", KvVersion::V2);
+        assert_eq!(url, "http://vault:8200/v1/secret/metadata/team/app/");
+    }
+
+    #[test]
+    fn query_value_reads_addr_and_namespace() {
+        let url = url::Url::parse("vault://secret/app?addr=https://vault-b:8200&namespace=team-a")
+            .unwrap();
+
+        assert_eq!(
+            query_value(&url, "addr").as_deref(),
+            Some("https://vault-b:8200")
+        );
+        assert_eq!(query_value(&url, "namespace").as_deref(), Some("team-a"));
+        assert_eq!(query_value(&url, "missing"), None);
+    }
+
+    #[test]
+    fn version_hints_from_url() {
+        let url = url::Url::parse("vault://secret/app/db?version=2&secret_version=3").unwrap();
+        let (kv_version, secret_version) = parse_version_hints(&url).unwrap();
+
+        assert_eq!(kv_version, Some(KvVersion::V2));
+        assert_eq!(secret_version, Some(3));
+    }
+
+    #[test]
+    fn no_version_hints() {
+        let url = url::Url::parse("vault://secret/app/db").unwrap();
+        let (kv_version, secret_version) = parse_version_hints(&url).unwrap();
+
+        assert_eq!(kv_version, None);
+        assert_eq!(secret_version, None);
+    }
+
+    #[test]
+    fn invalid_kv_version_hint() {
+        let url = url::Url::parse("vault://secret/app/db?version=3").unwrap();
+        assert!(matches!(
+            parse_version_hints(&url),
+            Err(VaultSourceError::InvalidKvVersion(_))
+        ));
+    }
+
+    #[test]
+    fn secret_version_rejected_for_kv_v1() {
+        let url = url::Url::parse("vault://secret/app/db?version=1&secret_version=3").unwrap();
+        assert!(matches!(
+            parse_version_hints(&url),
+            Err(VaultSourceError::SecretVersionRequiresKv2)
+        ));
+    }
+}
@@ -0,0 +1,292 @@
+use super::VaultSourceError;
+use crate::config::VaultTlsConfig;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+
+/// TLS settings for Vault connections, merged from the preset and the
+/// `VAULT_CACERT`, `VAULT_CAPATH`, `VAULT_CLIENT_CERT`, `VAULT_CLIENT_KEY` and
+/// `VAULT_SKIP_VERIFY` environment variables. Preset values win.
+#[derive(Debug, Default, PartialEq)]
+struct TlsSettings {
+    ca_cert: Option<String>,
+    ca_path: Option<String>,
+    client_cert: Option<String>,
+    client_key: Option<String>,
+    skip_verify: bool,
+}
+
+impl TlsSettings {
+    /// Merge the preset with the variables that `env` looks up.
+    fn resolve(config: &VaultTlsConfig, env: impl Fn(&str) -> Option<String>) -> Self {
+        let from_env = |name: &str| env(name).filter(|value| !value.is_empty());
+
+        Self {
+            ca_cert: config.ca_cert.clone().or_else(|| from_env("VAULT_CACERT")),
+            ca_path: config.ca_path.clone().or_else(|| from_env("VAULT_CAPATH")),
+            client_cert: config
+                .client_cert
+                .clone()
+                .or_else(|| from_env("VAULT_CLIENT_CERT")),
+            client_key: config
+                .client_key
+                .clone()
+                .or_else(|| from_env("VAULT_CLIENT_KEY")),
+            skip_verify: config
+                .skip_verify
+                .unwrap_or_else(|| from_env("VAULT_SKIP_VERIFY").is_some_and(|v| is_truthy(&v))),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        self.ca_cert.is_none()
+            && self.ca_path.is_none()
+            && self.client_cert.is_none()
+            && self.client_key.is_none()
+            && !self.skip_verify
+    }
+}
+
+/// Build a TLS client config, or `None` when nothing differs from the defaults.
+pub fn client_config(
+    config: &VaultTlsConfig,
+) -> Result<Option<Arc<ClientConfig>>, VaultSourceError> {
+    build_config(&TlsSettings::resolve(config, |name| env::var(name).ok()))
+}
+
+fn build_config(settings: &TlsSettings) -> Result<Option<Arc<ClientConfig>>, VaultSourceError> {
+    if settings.is_default() {
+        return Ok(None);
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(VaultSourceError::Tls)?;
+
+    let builder = if settings.skip_verify {
+        eprintln!("Warning: TLS certificate verification is disabled for Vault");
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+    } else {
+        builder.with_root_certificates(root_store(settings)?)
+    };
+
+    let config = match (&settings.client_cert, &settings.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = read_certificates(Path::new(cert_path))?;
+            let key = PrivateKeyDer::from_pem_file(key_path).map_err(|source| {
+                VaultSourceError::InvalidPem {
+                    path: key_path.clone(),
+                    source,
+                }
+            })?;
+
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(VaultSourceError::Tls)?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err(VaultSourceError::IncompleteClientCertificate),
+    };
+
+    Ok(Some(Arc::new(config)))
+}
+
+/// Trust the given CA certificates, or the bundled web PKI roots when none are set.
+fn root_store(settings: &TlsSettings) -> Result<RootCertStore, VaultSourceError> {
+    let mut store = RootCertStore::empty();
+
+    if settings.ca_cert.is_none() && settings.ca_path.is_none() {
+        store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        return Ok(store);
+    }
+
+    let mut certs = Vec::new();
+
+    if let Some(ca_cert) = &settings.ca_cert {
+        certs.extend(read_certificates(Path::new(ca_cert))?);
+    }
+
+    if let Some(ca_path) = &settings.ca_path {
+        let entries =
+            std::fs::read_dir(ca_path).map_err(|source| VaultSourceError::ReadCaPath {
+                path: ca_path.clone(),
+                source,
+            })?;
+
+        for entry in entries {
+            let path = entry
+                .map_err(|source| VaultSourceError::ReadCaPath {
+                    path: ca_path.clone(),
+                    source,
+                })?
+                .path();
+
+            if path.is_file() {
+                certs.extend(read_certificates(&path)?);
+            }
+        }
+    }
+
+    for cert in certs {
+        store.add(cert).map_err(VaultSourceError::Tls)?;
+    }
+
+    Ok(store)
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, VaultSourceError> {
+    let invalid = |source| VaultSourceError::InvalidPem {
+        path: path.display().to_string(),
+        source,
+    };
+
+    CertificateDer::pem_file_iter(path)
+        .map_err(invalid)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid)
+}
+
+fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.to_ascii_lowercase().as_str(),
+        "1" | "t" | "true" | "y" | "yes"
+    )
+}
+
+/// Accepts any server certificate, for `VAULT_SKIP_VERIFY`. Handshake
+/// signatures are still checked so the connection itself is sound.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truthy_values() {
+        assert!(is_truthy("1"));
+        assert!(is_truthy("true"));
+        assert!(is_truthy("TRUE"));
+        assert!(!is_truthy("0"));
+        assert!(!is_truthy("false"));
+        assert!(!is_truthy(""));
+    }
+
+    #[test]
+    fn preset_values_win_over_the_environment() {
+        let config = VaultTlsConfig {
+            ca_cert: Some("preset.pem".to_string()),
+            ..VaultTlsConfig::default()
+        };
+        let env = |name: &str| match name {
+            "VAULT_CACERT" => Some("env.pem".to_string()),
+            "VAULT_CAPATH" => Some(String::new()),
+            "VAULT_SKIP_VERIFY" => Some("yes".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(
+            TlsSettings::resolve(&config, env),
+            TlsSettings {
+                ca_cert: Some("preset.pem".to_string()),
+                skip_verify: true,
+                ..TlsSettings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn default_settings_build_no_config() {
+        assert!(build_config(&TlsSettings::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn skip_verify_builds_config() {
+        let settings = TlsSettings {
+            skip_verify: true,
+            ..TlsSettings::default()
+        };
+
+        assert!(build_config(&settings).unwrap().is_some());
+    }
+
+    #[test]
+    fn missing_ca_cert_is_an_error() {
+        let settings = TlsSettings {
+            ca_cert: Some("/definitely/not/a/ca.pem".to_string()),
+            ..TlsSettings::default()
+        };
+
+        assert!(matches!(
+            build_config(&settings),
+            Err(VaultSourceError::InvalidPem { .. })
+        ));
+    }
+
+    #[test]
+    fn client_cert_requires_key() {
+        let settings = TlsSettings {
+            client_cert: Some("/tmp/cert.pem".to_string()),
+            skip_verify: true,
+            ..TlsSettings::default()
+        };
+
+        assert!(matches!(
+            build_config(&settings),
+            Err(VaultSourceError::IncompleteClientCertificate)
+        ));
+    }
+}
@@ -0,0 +1,240 @@
+use crate::secrets::Secrets;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransformError {
+    #[error("keys '{first}' and '{second}' both become '{key}' after transforming")]
+    DuplicateKey {
+        key: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("key '{key}' would be replaced by '{renamed}'")]
+    NoRoundTrip { key: String, renamed: String },
+}
+
+/// A change of letter case or word separator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    /// `db_password` becomes `DB_PASSWORD`
+    Upper,
+
+    /// `DB_PASSWORD` becomes `db_password`
+    Lower,
+
+    /// `db-password` becomes `db_password`
+    Snake,
+
+    /// `db_password` becomes `db-password`
+    Kebab,
+}
+
+impl Case {
+    fn apply(self, key: &str) -> String {
+        match self {
+            Case::Upper => key.to_uppercase(),
+            Case::Lower => key.to_lowercase(),
+            Case::Snake => key.replace('-', "_"),
+            Case::Kebab => key.replace('_', "-"),
+        }
+    }
+
+    fn inverse(self) -> Self {
+        match self {
+            Case::Upper => Case::Lower,
+            Case::Lower => Case::Upper,
+            Case::Snake => Case::Kebab,
+            Case::Kebab => Case::Snake,
+        }
+    }
+}
+
+/// A single step of a key transform pipeline.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Put a prefix in front of every key
+    AddPrefix(String),
+
+    /// Remove a prefix from the keys that have it
+    StripPrefix(String),
+
+    /// Change the case or separator of every key
+    Case(Case),
+
+    /// Rename individual keys, from old to new name
+    Rename(BTreeMap<String, String>),
+}
+
+impl Transform {
+    fn apply(&self, key: &str) -> String {
+        match self {
+            Transform::AddPrefix(prefix) => format!("{prefix}{key}"),
+            Transform::StripPrefix(prefix) => key.strip_prefix(prefix).unwrap_or(key).to_string(),
+            Transform::Case(case) => case.apply(key),
+            Transform::Rename(names) => names.get(key).cloned().unwrap_or_else(|| key.to_string()),
+        }
+    }
+
+    fn inverse(&self) -> Self {
+        match self {
+            Transform::AddPrefix(prefix) => Transform::StripPrefix(prefix.clone()),
+            Transform::StripPrefix(prefix) => Transform::AddPrefix(prefix.clone()),
+            Transform::Case(case) => Transform::Case(case.inverse()),
+            Transform::Rename(names) => Transform::Rename(
+                names
+                    .iter()
+                    .map(|(old, new)| (new.clone(), old.clone()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Steps applied in order to every key between reading and writing secrets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Transforms(pub Vec<Transform>);
+
+impl Transforms {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The pipeline that undoes this one, for the opposite sync direction.
+    pub fn inverse(&self) -> Self {
+        Transforms(self.0.iter().rev().map(Transform::inverse).collect())
+    }
+
+    pub fn key(&self, key: &str) -> String {
+        self.0
+            .iter()
+            .fold(key.to_string(), |key, transform| transform.apply(&key))
+    }
+
+    /// Check that each key, renamed by the inverse of this pipeline and then
+    /// by this pipeline, is unchanged. Steps such as `strip_prefix` or a case
+    /// change only undo each other for some keys.
+    pub fn check_round_trip<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a String>,
+    ) -> Result<(), TransformError> {
+        let inverse = self.inverse();
+
+        for key in keys {
+            let renamed = self.key(&inverse.key(key));
+            if renamed != *key {
+                return Err(TransformError::NoRoundTrip {
+                    key: key.clone(),
+                    renamed,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rename every secret, failing if two keys end up with the same name.
+    pub fn apply(&self, secrets: Secrets) -> Result<Secrets, TransformError> {
+        if self.is_empty() {
+            return Ok(secrets);
+        }
+
+        let mut renamed = Secrets::new();
+        let mut original_of: BTreeMap<String, String> = BTreeMap::new();
+
+        for (key, value) in secrets.content {
+            let new_key = self.key(&key);
+
+            if let Some(first) = original_of.insert(new_key.clone(), key.clone()) {
+                return Err(TransformError::DuplicateKey {
+                    key: new_key,
+                    first,
+                    second: key,
+                });
+            }
+
+            renamed.content.insert(new_key, value);
+        }
+
+        Ok(renamed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline() -> Transforms {
+        serde_json::from_str(
+            r#"[
+                { "rename": { "db_pass": "db_password" } },
+                { "case": "upper" },
+                { "add_prefix": "APP_" }
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn steps_apply_in_order() {
+        let transforms = pipeline();
+        assert_eq!(transforms.key("db_pass"), "APP_DB_PASSWORD");
+        assert_eq!(transforms.key("api_key"), "APP_API_KEY");
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let transforms = pipeline();
+        let inverse = transforms.inverse();
+
+        for key in ["db_pass", "api_key"] {
+            assert_eq!(inverse.key(&transforms.key(key)), key);
+        }
+    }
+
+    #[test]
+    fn kebab_and_snake_are_inverses() {
+        let transforms = Transforms(vec![Transform::Case(Case::Kebab)]);
+        assert_eq!(transforms.key("db_password"), "db-password");
+        assert_eq!(transforms.inverse().key("db-password"), "db_password");
+    }
+
+    #[test]
+    fn keys_without_the_stripped_prefix_do_not_round_trip() {
+        // A pull strips the prefix, and the mirrored push adds it back
+        let pull = Transforms(vec![Transform::StripPrefix("APP_".to_string())]);
+        let push = pull.inverse();
+        let target = ["APP_X".to_string(), "OTHER".to_string()];
+
+        assert!(push.check_round_trip(&target[..1]).is_ok());
+        assert!(matches!(
+            push.check_round_trip(&target),
+            Err(TransformError::NoRoundTrip { key, renamed }) if key == "OTHER" && renamed == "APP_OTHER"
+        ));
+    }
+
+    #[test]
+    fn mixed_case_keys_do_not_round_trip() {
+        let push = Transforms(vec![Transform::Case(Case::Upper)]).inverse();
+
+        assert!(push.check_round_trip(&["db_password".to_string()]).is_ok());
+        assert!(push.check_round_trip(&["Db_Password".to_string()]).is_err());
+    }
+
+    #[test]
+    fn colliding_keys_are_an_error() {
+        let mut secrets = Secrets::new();
+        secrets.content.insert("db".to_string(), "1".to_string());
+        secrets.content.insert("DB".to_string(), "2".to_string());
+
+        let transforms = Transforms(vec![Transform::Case(Case::Upper)]);
+        assert!(matches!(
+            transforms.apply(secrets),
+            Err(TransformError::DuplicateKey { .. })
+        ));
+    }
+}